@@ -10,7 +10,7 @@ use syn::*;
 pub enum FieldKind {
     Flags { repr: PrimitiveKind },
     Enum { repr: PrimitiveKind },
-    Payload { ty: Type, size: usize },
+    Payload { ty: Type, size: Option<usize> },
     Raw { ty: Type },
 }
 
@@ -19,8 +19,91 @@ pub struct FieldInfo {
     pub ident: Option<Ident>,
     pub variable: Ident,
     pub name: String,
+    /// True if the name was given with `#[name]` instead of taken from the ident.
+    pub explicit_name: bool,
+    /// How to access this field on `self`.
+    pub member: Member,
     pub span: Span,
-    pub idx: usize,
+    pub optional: bool,
+    /// Bounds given with `#[range(min..=max)]`.
+    pub range: Option<(i64, i64)>,
+    /// Function given with `#[validate(path)]`.
+    pub validate: Option<Path>,
+}
+
+/// Returns `T` if `ty` is `Option<T>`.
+fn get_option_inner(ty: &Type) -> Option<&Type> {
+    let path = match ty {
+        Type::Path(path) if path.qself.is_none() => &path.path,
+        _ => return None,
+    };
+
+    let segment = path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) if args.args.len() == 1 => match &args.args[0] {
+            GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The argument of the `#[range(min..=max)]` attribute.
+struct RangeAttr {
+    min: i64,
+    max: i64,
+}
+
+/// Parses an integer literal with an optional minus sign.
+fn parse_signed(input: parse::ParseStream) -> Result<i64> {
+    let negative = input.parse::<Option<Token![-]>>()?.is_some();
+    let value = input.parse::<LitInt>()?.base10_parse::<i64>()?;
+    Ok(if negative { -value } else { value })
+}
+
+impl parse::Parse for RangeAttr {
+    fn parse(input: parse::ParseStream) -> Result<Self> {
+        let min = parse_signed(input)?;
+        input.parse::<Token![..=]>()?;
+        let max = parse_signed(input)?;
+        Ok(RangeAttr { min, max })
+    }
+}
+
+/// Parses the `#[range(min..=max)]` attribute.
+fn get_range_attr(attrs: &[Attribute]) -> Option<(i64, i64)> {
+    let attr = attrs.iter().rfind(|&attr| attr.path.is_ident("range"))?;
+    match attr.parse_args::<RangeAttr>() {
+        Ok(range) => Some((range.min, range.max)),
+        Err(_) => {
+            emit_error!(attr, "range attribute must be an inclusive range like 0..=16");
+            None
+        }
+    }
+}
+
+/// Parses the `#[size(N)]` attribute, which can be placed on structs and fields.
+pub fn get_size_attr(attrs: &[Attribute]) -> Option<usize> {
+    attrs
+        .iter()
+        .rfind(|&attr| attr.path.is_ident("size"))
+        .and_then(|attr| match attr.parse_args::<LitInt>() {
+            Ok(s) => match s.base10_parse::<usize>() {
+                Ok(s) => Some(s),
+                Err(_) => {
+                    emit_error!(attr, "invalid size attribute");
+                    None
+                }
+            },
+            Err(_) => {
+                emit_error!(attr, "invalid size attribute");
+                None
+            }
+        })
 }
 
 pub fn get_info_for_field(idx: usize, field: &Field) -> Option<FieldInfo> {
@@ -37,50 +120,44 @@ pub fn get_info_for_field(idx: usize, field: &Field) -> Option<FieldInfo> {
         .attrs
         .iter()
         // actual helper attribute is called "format" to avoid conflict
-        .filter(|&attr| attr.path.is_ident("format"))
-        .last()
+        .rfind(|&attr| attr.path.is_ident("format"))
         .and_then(|attr| match attr.parse_args::<Type>() {
             Ok(ty) => match ty.try_into() {
                 Ok(ty) => Some(ty),
                 _ => {
                     emit_error!(attr, "invalid repr attribute");
-                    return None;
+                    None
                 }
             },
             _ => {
                 emit_error!(attr, "invalid repr attribute");
-                return None;
+                None
             }
         });
 
-    let size: Option<usize> = field
-        .attrs
-        .iter()
-        .filter(|&attr| attr.path.is_ident("size"))
-        .last()
-        .and_then(|attr| match attr.parse_args::<LitInt>() {
-            Ok(s) => match s.base10_parse::<usize>() {
-                Ok(s) => Some(s),
-                Err(_) => {
-                    emit_error!(attr, "invalid size attribute");
-                    return None;
-                }
-            },
-            Err(_) => {
-                emit_error!(attr, "invalid size attribute");
+    let size = get_size_attr(&field.attrs);
+
+    let optional = field.attrs.iter().any(|attr| attr.path.is_ident("optional"));
+
+    // optional fields are parsed and serialized as the type inside the `Option`
+    let ty = if optional {
+        match get_option_inner(&field.ty) {
+            Some(ty) => ty.clone(),
+            None => {
+                emit_error!(field.ty, "optional fields must have type Option<T>");
                 return None;
             }
-        });
+        }
+    } else {
+        field.ty.clone()
+    };
 
     let kind = field
         .attrs
         .iter()
-        .filter(|&attr| attr.path.is_ident("kind"))
-        .last()
+        .rfind(|&attr| attr.path.is_ident("kind"))
         .and_then(|attr| match attr.parse_args::<Ident>() {
-            Ok(ident) if ident == "raw" => Some(FieldKind::Raw {
-                ty: field.ty.clone(),
-            }),
+            Ok(ident) if ident == "raw" => Some(FieldKind::Raw { ty: ty.clone() }),
             Ok(ident) if ident == "enumeration" => Some(FieldKind::Enum {
                 repr: match repr {
                     Some(repr) => repr,
@@ -100,18 +177,12 @@ pub fn get_info_for_field(idx: usize, field: &Field) -> Option<FieldInfo> {
                 },
             }),
             Ok(ident) if ident == "payload" => Some(FieldKind::Payload {
-                size: match size {
-                    Some(size) => size,
-                    _ => {
-                        emit_error!(attr, "size attribute is required for raw fields");
-                        return None;
-                    }
-                },
-                ty: field.ty.clone(),
+                size,
+                ty: ty.clone(),
             }),
             _ => {
                 emit_error!(attr, "invalid kind attribute");
-                return None;
+                None
             }
         });
 
@@ -123,19 +194,20 @@ pub fn get_info_for_field(idx: usize, field: &Field) -> Option<FieldInfo> {
         }
     };
 
-    let name: Option<String> = field
+    let explicit_name: Option<String> = field
         .attrs
         .iter()
-        .filter(|&attr| attr.path.is_ident("name"))
-        .last()
+        .rfind(|&attr| attr.path.is_ident("name"))
         .and_then(|attr| match attr.parse_args::<LitStr>() {
             Ok(name) => Some(name.value()),
             _ => {
                 emit_error!(attr, "invalid name attribute");
-                return None;
+                None
             }
-        })
-        .or(field.ident.clone().map(|i| i.to_string().to_uppercase()));
+        });
+
+    let explicit = explicit_name.is_some();
+    let name = explicit_name.or(field.ident.clone().map(|i| i.to_string().to_uppercase()));
 
     let name = match name {
         Some(name) => name,
@@ -148,12 +220,35 @@ pub fn get_info_for_field(idx: usize, field: &Field) -> Option<FieldInfo> {
         }
     };
 
+    let range = get_range_attr(&field.attrs);
+
+    let validate = field
+        .attrs
+        .iter()
+        .rfind(|&attr| attr.path.is_ident("validate"))
+        .and_then(|attr| match attr.parse_args::<Path>() {
+            Ok(path) => Some(path),
+            _ => {
+                emit_error!(attr, "invalid validate attribute");
+                None
+            }
+        });
+
+    let member = match &field.ident {
+        Some(ident) => Member::Named(ident.clone()),
+        None => Member::Unnamed(Index::from(idx)),
+    };
+
     Some(FieldInfo {
         kind,
-        idx,
         ident: field.ident.clone(),
         name,
+        explicit_name: explicit,
+        member,
         variable,
         span: field.span(),
+        optional,
+        range,
+        validate,
     })
 }
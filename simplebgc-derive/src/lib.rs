@@ -20,11 +20,15 @@ mod field;
 mod primitive;
 
 /// Add this to any structure that represents a payload
-/// for a SimpleBGC command.
+/// for a SimpleBGC command. This implements `Payload` and `PayloadSize` for it.
 ///
 /// If a command contains repeated data, you can consider these as
 /// "sub-payloads" and refactor them into their own structure.
 ///
+/// Before each member is parsed, the generated code checks that enough bytes are left
+/// in the payload, so that a truncated payload results in
+/// `PayloadParseError::InsufficientPayload` instead of a panic.
+///
 /// ## `#[kind]`
 /// This helper attribute must be present on every member of a `BgcPayload` struct. It will
 /// tell the derive macro how to process this member.
@@ -40,20 +44,22 @@ mod primitive;
 ///
 /// ### `#[kind(payload)]`
 /// Indicates that this struct member is a sub-payload. The member's type must implement
-/// [`simplebgc::payload`].
+/// [`simplebgc::payload`] and `PayloadSize`.
 ///
 /// ### `#[kind(raw)]`
 /// Indicates that this struct member is a primitive value. The member's type must be a primitive
 /// integer, boolean, array of `u8`, or tuple of primitives.
 ///
 /// ## `#[size]`
-/// This helper attribute specifies the number of bytes that a sub-payload takes up in the
-/// serialized representation, so that it can be known how many bytes to split off and give
-/// to `Payload::from_bytes`. It is required for members with `kind(payload)` and has
-/// no effect for all others. It accepts one argument: a number representing the size of this
-/// sub-payload.
+/// This helper attribute states the number of bytes that a sub-payload or a whole payload
+/// takes up in the serialized representation. Sizes are computed automatically through
+/// `PayloadSize`, so this attribute is only a check: if the computed size does not match,
+/// compilation fails. It accepts one argument: a number representing the expected size. On a
+/// member, it has no effect unless the member has `kind(payload)`. On a struct, it is compared
+/// against the size with all optional members present, which is useful for checking a payload
+/// against the byte count published in the spec.
 ///
-/// ```no_run
+/// ```ignore
 /// # struct Example {
 /// #[kind(payload)]
 /// #[size(18)]
@@ -64,7 +70,7 @@ mod primitive;
 /// ## `#[format]`
 /// This helper attribute specifies the underlying representation of enum and flags members.
 /// It takes one argument: a type, which must be a primitive integer type (`u8`, `i8`, `u16`, etc.).
-/// ```no_run
+/// ```ignore
 /// # struct Example {
 ///     #[kind(enumeration)]
 ///     #[format(u8)]
@@ -72,13 +78,47 @@ mod primitive;
 /// # }
 /// ```
 ///
+/// ## `#[optional]`
+/// This helper attribute marks a member that was added to the payload in a later firmware
+/// version. The member's type must be `Option<T>`, and the `kind`, `format` and `size`
+/// attributes describe `T`. When parsing, the member is `None` if the payload has already
+/// ended, and when serializing, the payload ends at the first optional member that is `None`,
+/// so later members are not written even if they are `Some`. Optional members must come after
+/// all required members.
+///
+/// ```ignore
+/// # struct Example {
+///     #[kind(raw)]
+///     #[optional]
+///     pub base_frw_ver: Option<u16>,
+/// # }
+/// ```
+///
+/// ## `#[range]`
+/// This helper attribute gives the values allowed for a member in the SimpleBGC spec, as an
+/// inclusive range. It does not affect parsing; the range is checked by
+/// `PayloadFields::validate`. On a sub-payload, array or tuple, every element is checked.
+///
+/// ```ignore
+/// # struct Example {
+///     #[kind(raw)]
+///     #[range(0..=16)]
+///     pub rc_lpf: u8,
+/// # }
+/// ```
+///
+/// ## `#[validate]`
+/// This helper attribute names a function taking a reference to the member and returning
+/// whether its value is allowed, for checks that a range cannot express. Like `#[range]`, it
+/// is checked by `PayloadFields::validate`.
+///
 /// ## `#[name]`
 /// This helper attribute specifies the name of this item as specified in the SimpleBGC spec.
 /// This is to be used in error messages in case deserialization fails. If it is not provided,
 /// it is assumed that the spec name is the same as the member name. This attribute is required
 /// for members of tuple structs.
 ///
-/// ```no_run
+/// ```ignore
 /// # struct Example {
 ///     #[kind(enumeration)]
 ///     #[name("RC_MAP_FC_ROLL")]
@@ -87,13 +127,13 @@ mod primitive;
 /// # }
 /// ```
 #[proc_macro_error]
-#[proc_macro_derive(BgcPayload, attributes(kind, size, name, format))]
+#[proc_macro_derive(BgcPayload, attributes(kind, size, name, format, optional, range, validate))]
 pub fn payload_derive(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     let ty = input.ident;
+    let attrs = input.attrs;
 
     dummy_const_trick(
-        &ty,
         match input.data {
             Data::Struct(data) => match data.fields {
                 Fields::Named(fields) => {
@@ -104,27 +144,36 @@ pub fn payload_derive(input: TokenStream) -> TokenStream {
                         .filter_map(|(i, field)| get_info_for_field(i, field))
                         .collect::<Vec<_>>();
 
+                    check_optional_fields_are_trailing(&fields_info);
+                    let size_impl = get_size_impl(&ty, &attrs, &fields_info);
+                    let fields_impl = get_fields_impl(&ty, &fields_info);
+
                     let parse_stmts = fields_info
                         .iter()
-                        .filter_map(|info| get_parser_for_field(&info))
+                        .filter_map(get_checked_parser_for_field)
                         .collect::<Vec<_>>();
 
-                    let ser_stmts = fields_info
-                        .iter()
-                        .filter_map(|info| get_serializer_for_field(&info))
-                        .collect::<Vec<_>>();
+                    let ser_stmts = get_serializers_for_fields(&fields_info);
 
                     let vars = fields_info
                         .iter()
                         .map(|info| &info.variable)
                         .collect::<Vec<_>>();
 
+                    let bindings = fields_info
+                        .iter()
+                        .map(get_binding_for_field)
+                        .collect::<Vec<_>>();
+
                     let fields = fields_info
                         .iter()
-                        .map(|info| (&info.ident).as_ref().unwrap())
+                        .map(|info| info.ident.as_ref().unwrap())
                         .collect::<Vec<_>>();
 
                     quote! {
+                        #size_impl
+                        #fields_impl
+
                         impl Payload for #ty {
                             fn from_bytes(mut _b: Bytes) -> Result<Self, PayloadParseError>
                             where
@@ -142,7 +191,7 @@ pub fn payload_derive(input: TokenStream) -> TokenStream {
                                 Self: Sized,
                             {
                                 let mut _b = BytesMut::new();
-                                let &#ty { #(#vars),* } = self;
+                                let &#ty { #(#bindings),* } = self;
 
                                 #(#ser_stmts)*
 
@@ -159,22 +208,31 @@ pub fn payload_derive(input: TokenStream) -> TokenStream {
                         .filter_map(|(i, field)| get_info_for_field(i, field))
                         .collect();
 
+                    check_optional_fields_are_trailing(&fields_info);
+                    let size_impl = get_size_impl(&ty, &attrs, &fields_info);
+                    let fields_impl = get_fields_impl(&ty, &fields_info);
+
                     let parse_stmts: Vec<_> = fields_info
                         .iter()
-                        .filter_map(|info| get_parser_for_field(&info))
+                        .filter_map(get_checked_parser_for_field)
                         .collect();
 
-                    let ser_stmts = fields_info
-                        .iter()
-                        .filter_map(|info| get_serializer_for_field(&info))
-                        .collect::<Vec<_>>();
+                    let ser_stmts = get_serializers_for_fields(&fields_info);
 
                     let vars = fields_info
                         .iter()
                         .map(|info| &info.variable)
                         .collect::<Vec<_>>();
 
+                    let bindings = fields_info
+                        .iter()
+                        .map(get_binding_for_field)
+                        .collect::<Vec<_>>();
+
                     quote! {
+                        #size_impl
+                        #fields_impl
+
                         impl Payload for #ty {
                             fn from_bytes(mut _b: Bytes) -> Result<Self, PayloadParseError>
                             where
@@ -192,7 +250,7 @@ pub fn payload_derive(input: TokenStream) -> TokenStream {
                                 Self: Sized,
                             {
                                 let mut _b = BytesMut::new();
-                                let &#ty ( #(#vars),* ) = self;
+                                let &#ty ( #(#bindings),* ) = self;
 
                                 #(#ser_stmts)*
 
@@ -212,16 +270,17 @@ pub fn payload_derive(input: TokenStream) -> TokenStream {
 
 // This trick is taken from num_traits:
 // https://github.com/rust-num/num-derive/blob/bafa54c551a9c89d005eb9a41d015a6cca6b614f/src/lib.rs#L49
-fn dummy_const_trick<T: quote::ToTokens>(name: &Ident, exp: T) -> TokenStream2 {
-    let dummy_const = format_ident!("__IMPL_PAYLOAD_FOR_{}", name);
+fn dummy_const_trick<T: quote::ToTokens>(exp: T) -> TokenStream2 {
     quote! {
         #[allow(non_upper_case_globals, unused_qualifications)]
-        const #dummy_const: () = {
+        const _: () = {
             use bytes::{Bytes, BytesMut, Buf, BufMut};
             #[allow(unused_imports)]
             use enumflags2::{BitFlags};
             #[allow(unused_imports)]
             use num_traits::{FromPrimitive, ToPrimitive};
+            #[allow(unused_imports)]
+            use std::convert::TryInto;
             #exp
         };
     }
@@ -230,14 +289,496 @@ fn dummy_const_trick<T: quote::ToTokens>(name: &Ident, exp: T) -> TokenStream2 {
 const ERR_RAW_PRIMITIVE: &str =
     "field must be primitive type, tuple of primitive types, or array of u8 for raw values";
 
+/// Returns an expression for the number of bytes this field takes up in the payload.
+fn get_size_for_field(info: &FieldInfo) -> Option<TokenStream2> {
+    match &info.kind {
+        FieldKind::Payload { ty, .. } => Some(quote!(<#ty as PayloadSize>::SIZE)),
+        FieldKind::Flags { repr } | FieldKind::Enum { repr } => {
+            let size = repr.size();
+            Some(quote!(#size))
+        }
+        FieldKind::Raw { ty } => get_size_for_raw(ty),
+    }
+}
+
+fn get_size_for_raw(ty: &Type) -> Option<TokenStream2> {
+    if let Ok(repr) = PrimitiveKind::try_from(ty.clone()) {
+        let size = repr.size();
+        return Some(quote!(#size));
+    }
+
+    match ty {
+        Type::Array(ty) => {
+            let len = &ty.len;
+            Some(quote!((#len)))
+        }
+        Type::Tuple(ty) => {
+            let sizes = ty
+                .elems
+                .iter()
+                .map(get_size_for_raw)
+                .collect::<Option<Vec<_>>>()?;
+
+            Some(quote!((0 #(+ #sizes)*)))
+        }
+        _ => None,
+    }
+}
+
+/// Generates the `PayloadSize` impl for a payload struct, along with compile-time checks
+/// for any sizes that were given explicitly with `#[size(N)]`.
+fn get_size_impl(ty: &Ident, attrs: &[Attribute], fields_info: &[FieldInfo]) -> TokenStream2 {
+    let sizes = fields_info
+        .iter()
+        .filter_map(get_size_for_field)
+        .collect::<Vec<_>>();
+
+    let min_sizes = fields_info
+        .iter()
+        .filter(|info| !info.optional)
+        .filter_map(get_size_for_field)
+        .collect::<Vec<_>>();
+
+    let field_checks = fields_info.iter().filter_map(|info| match &info.kind {
+        FieldKind::Payload {
+            ty: field_ty,
+            size: Some(size),
+        } => {
+            let span = info.span;
+            let msg = format!("size of {} does not match its size attribute", info.name);
+            Some(quote_spanned! {span=>
+                const _: () = assert!(<#field_ty as PayloadSize>::SIZE == #size, #msg);
+            })
+        }
+        _ => None,
+    });
+
+    let struct_check = get_size_attr(attrs).map(|size| {
+        let msg = format!("size of {} does not match its size attribute", ty);
+        quote! {
+            const _: () = assert!(<#ty as PayloadSize>::SIZE == #size, #msg);
+        }
+    });
+
+    quote! {
+        impl PayloadSize for #ty {
+            const SIZE: usize = 0 #(+ #sizes)*;
+            const MIN_SIZE: usize = 0 #(+ #min_sizes)*;
+        }
+
+        #(#field_checks)*
+        #struct_check
+    }
+}
+
+/// Generates the `PayloadFields` impl for a payload struct, which gives access to each
+/// field by its spec name.
+fn get_fields_impl(ty: &Ident, fields_info: &[FieldInfo]) -> TokenStream2 {
+    let visit_stmts = fields_info
+        .iter()
+        .filter_map(get_visitor_for_field)
+        .collect::<Vec<_>>();
+
+    let set_stmts = fields_info
+        .iter()
+        .enumerate()
+        .filter_map(|(i, info)| get_setter_for_field(info, &fields_info[..i]))
+        .collect::<Vec<_>>();
+
+    let validate_stmts = fields_info
+        .iter()
+        .filter_map(get_validator_for_field)
+        .collect::<Vec<_>>();
+
+    quote! {
+        impl PayloadFields for #ty {
+            fn visit_fields(
+                &self,
+                _path: &FieldPath,
+                _visit: &mut dyn FnMut(String, FieldValue),
+            ) {
+                #(#visit_stmts)*
+            }
+
+            fn set_field(
+                &mut self,
+                _path: &FieldPath,
+                _name: &str,
+                _value: &FieldValue,
+            ) -> Result<bool, FieldError> {
+                #(#set_stmts)*
+                Ok(false)
+            }
+
+            fn validate_fields(&self, _path: &FieldPath, _errors: &mut Vec<FieldError>) {
+                #(#validate_stmts)*
+            }
+        }
+    }
+}
+
+/// Returns an expression converting `val`, a raw primitive, to a `FieldValue`.
+fn get_raw_field_value(repr: PrimitiveKind, val: &TokenStream2) -> TokenStream2 {
+    match repr {
+        PrimitiveKind::Bool => quote!(FieldValue::Bool(#val)),
+        _ => quote!(FieldValue::Int(#val as i64)),
+    }
+}
+
+/// Returns an expression converting `_value` to a raw primitive.
+fn get_raw_field_conversion(repr: PrimitiveKind) -> TokenStream2 {
+    match repr {
+        PrimitiveKind::Bool => quote!(_value.to_bool(_name)?),
+        _ => quote!(_value.to_int(_name)?),
+    }
+}
+
+fn get_visitor_for_field(info: &FieldInfo) -> Option<TokenStream2> {
+    let span = info.span;
+    let name = &info.name;
+    let explicit = info.explicit_name;
+    let member = &info.member;
+
+    let val = if info.optional {
+        quote!((*_v))
+    } else {
+        quote!(self.#member)
+    };
+
+    let stmt = match &info.kind {
+        FieldKind::Payload { .. } | FieldKind::Flags { .. } => quote_spanned! {span=>
+            PayloadFields::visit_fields(&#val, &_path.child(#name, #explicit), _visit);
+        },
+        FieldKind::Enum { .. } => quote_spanned! {span=>
+            _visit(
+                _path.name(#name, #explicit),
+                FieldValue::Enum(format!("{:?}", #val)),
+            );
+        },
+        FieldKind::Raw { ty } => {
+            if let Ok(repr) = PrimitiveKind::try_from(ty.clone()) {
+                let value = get_raw_field_value(repr, &val);
+                quote_spanned! {span=>
+                    _visit(_path.name(#name, #explicit), #value);
+                }
+            } else {
+                match ty {
+                    Type::Array(_) => quote_spanned! {span=>
+                        _visit(_path.name(#name, #explicit), FieldValue::Bytes(#val.to_vec()));
+                    },
+                    Type::Tuple(ty) => {
+                        let stmts = ty
+                            .elems
+                            .iter()
+                            .enumerate()
+                            .map(|(elem_idx, elem_ty)| {
+                                let repr = PrimitiveKind::try_from(elem_ty.clone()).ok()?;
+                                let index = Index::from(elem_idx);
+                                let index_name = elem_idx.to_string();
+                                let value = get_raw_field_value(repr, &quote!(#val.#index));
+                                Some(quote_spanned! {span=>
+                                    _visit(
+                                        _path.index(#index_name).name(#name, #explicit),
+                                        #value,
+                                    );
+                                })
+                            })
+                            .collect::<Option<Vec<_>>>()?;
+
+                        quote!(#(#stmts)*)
+                    }
+                    _ => return None,
+                }
+            }
+        }
+    };
+
+    if info.optional {
+        Some(quote_spanned! {span=>
+            if let Some(_v) = &self.#member {
+                #stmt
+            }
+        })
+    } else {
+        Some(stmt)
+    }
+}
+
+/// Checks the `#[range]` and `#[validate]` attributes of a field, and validates
+/// sub-payloads.
+fn get_validator_for_field(info: &FieldInfo) -> Option<TokenStream2> {
+    let span = info.span;
+    let name = &info.name;
+    let explicit = info.explicit_name;
+    let member = &info.member;
+    let mut stmts = Vec::new();
+
+    if let FieldKind::Payload { .. } = info.kind {
+        stmts.push(if info.optional {
+            quote_spanned! {span=>
+                if let Some(_v) = &self.#member {
+                    PayloadFields::validate_fields(_v, &_path.child(#name, #explicit), _errors);
+                }
+            }
+        } else {
+            quote_spanned! {span=>
+                PayloadFields::validate_fields(&self.#member, &_path.child(#name, #explicit), _errors);
+            }
+        });
+    }
+
+    // both checks go through the visitor, so that every axis or element of the
+    // field is checked and reported with its own name
+    if let Some((min, max)) = &info.range {
+        let visit = get_visitor_for_field(info)?;
+        stmts.push(quote_spanned! {span=>
+            {
+                let _visit: &mut dyn FnMut(String, FieldValue) = &mut |name, value| {
+                    if let Err(err) = value.check_range(&name, #min, #max) {
+                        _errors.push(err);
+                    }
+                };
+                #visit
+            }
+        });
+    }
+
+    if let Some(validate) = &info.validate {
+        let visit = get_visitor_for_field(info)?;
+        let invalid = if info.optional {
+            quote!(self.#member.as_ref().map_or(false, |_v| !#validate(_v)))
+        } else {
+            quote!(!#validate(&self.#member))
+        };
+        stmts.push(quote_spanned! {span=>
+            if #invalid {
+                let _visit: &mut dyn FnMut(String, FieldValue) = &mut |name, value| {
+                    _errors.push(FieldError::InvalidValue { name, value });
+                };
+                #visit
+            }
+        });
+    }
+
+    if stmts.is_empty() {
+        None
+    } else {
+        Some(quote!(#(#stmts)*))
+    }
+}
+
+/// `previous` holds the fields that come before this one in the payload.
+fn get_setter_for_field(info: &FieldInfo, previous: &[FieldInfo]) -> Option<TokenStream2> {
+    let span = info.span;
+    let name = &info.name;
+    let explicit = info.explicit_name;
+    let member = &info.member;
+
+    // an optional field is only written if every optional field before it is,
+    // so it cannot be set while one of them is `None`
+    let checks = previous
+        .iter()
+        .filter(|_| info.optional)
+        .filter(|prev| prev.optional)
+        .map(|prev| {
+            let prev_name = &prev.name;
+            let prev_explicit = prev.explicit_name;
+            let prev_member = &prev.member;
+            quote_spanned! {span=>
+                if self.#prev_member.is_none() {
+                    return Err(FieldError::MissingOptional {
+                        name: _name.into(),
+                        missing: _path.name(#prev_name, #prev_explicit),
+                    });
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // wraps the new value of a leaf field, so that optional fields can be set
+    // even if they are currently `None`
+    let assign = |value: TokenStream2| {
+        let value = if info.optional {
+            quote!(Some(#value))
+        } else {
+            value
+        };
+
+        quote_spanned! {span=>
+            if _name == _path.name(#name, #explicit) {
+                #(#checks)*
+                self.#member = #value;
+                return Ok(true);
+            }
+        }
+    };
+
+    match &info.kind {
+        FieldKind::Payload { .. } | FieldKind::Flags { .. } => {
+            let set = quote_spanned! {span=>
+                if PayloadFields::set_field(_v, &_path.child(#name, #explicit), _name, _value)? {
+                    return Ok(true);
+                }
+            };
+
+            if info.optional {
+                Some(quote_spanned! {span=>
+                    if let Some(_v) = &mut self.#member {
+                        #set
+                    }
+                })
+            } else {
+                Some(quote_spanned! {span=>
+                    {
+                        let _v = &mut self.#member;
+                        #set
+                    }
+                })
+            }
+        }
+        FieldKind::Enum { repr } => {
+            let repr = format_ident!("{}", repr);
+            Some(assign(quote! {
+                _value.to_enum(_name, #repr::MIN as i64, #repr::MAX as i64)?
+            }))
+        }
+        FieldKind::Raw { ty } => {
+            if let Ok(repr) = PrimitiveKind::try_from(ty.clone()) {
+                return Some(assign(get_raw_field_conversion(repr)));
+            }
+
+            match ty {
+                Type::Array(_) => Some(assign(quote!(_value.to_array(_name)?))),
+                Type::Tuple(ty) if !info.optional => {
+                    let stmts = ty
+                        .elems
+                        .iter()
+                        .enumerate()
+                        .map(|(elem_idx, elem_ty)| {
+                            let repr = PrimitiveKind::try_from(elem_ty.clone()).ok()?;
+                            let index = Index::from(elem_idx);
+                            let index_name = elem_idx.to_string();
+                            let value = get_raw_field_conversion(repr);
+                            Some(quote_spanned! {span=>
+                                if _name == _path.index(#index_name).name(#name, #explicit) {
+                                    self.#member.#index = #value;
+                                    return Ok(true);
+                                }
+                            })
+                        })
+                        .collect::<Option<Vec<_>>>()?;
+
+                    Some(quote!(#(#stmts)*))
+                }
+                Type::Tuple(_) => {
+                    emit_error!(ty, "optional tuples are not supported");
+                    None
+                }
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Like [`get_parser_for_field`], but first makes sure that there are
+/// enough bytes left for this field so that parsing never panics.
+fn get_checked_parser_for_field(info: &FieldInfo) -> Option<TokenStream2> {
+    let parser = get_parser_for_field(info)?;
+    let size = get_size_for_field(info)?;
+    let span = info.span;
+    let name = &info.name;
+    let var = &info.variable;
+
+    let parser = quote_spanned! {span=>
+        if _b.remaining() < #size {
+            return Err(PayloadParseError::InsufficientPayload {
+                name: #name.into(),
+                expected: #size,
+                actual: _b.remaining(),
+            });
+        }
+        #parser
+    };
+
+    if info.optional {
+        Some(quote_spanned! {span=>
+            let #var = if _b.has_remaining() {
+                #parser
+                Some(#var)
+            } else {
+                None
+            };
+        })
+    } else {
+        Some(parser)
+    }
+}
+
+/// Sub-payloads are bound by reference when serializing, because they are not
+/// necessarily `Copy`.
+fn get_binding_for_field(info: &FieldInfo) -> TokenStream2 {
+    let var = &info.variable;
+
+    match &info.kind {
+        FieldKind::Payload { .. } => quote!(ref #var),
+        _ => quote!(#var),
+    }
+}
+
+/// Serializes every field in order. Optional fields are nested so that the
+/// first one that is `None` ends the payload; writing a later one would put it
+/// at the offset of the missing field.
+fn get_serializers_for_fields(fields_info: &[FieldInfo]) -> Vec<TokenStream2> {
+    let mut stmts = fields_info
+        .iter()
+        .filter(|info| !info.optional)
+        .filter_map(get_serializer_for_field)
+        .collect::<Vec<_>>();
+
+    let optional = fields_info
+        .iter()
+        .rev()
+        .filter(|info| info.optional)
+        .fold(None, |rest: Option<TokenStream2>, info| {
+            let serializer = get_serializer_for_field(info);
+            let span = info.span;
+            let var = &info.variable;
+            Some(quote_spanned! {span=>
+                if let Some(#var) = #var {
+                    #serializer
+                    #rest
+                }
+            })
+        });
+
+    stmts.extend(optional);
+    stmts
+}
+
+/// Optional fields can only be told apart by whether the payload has ended, so
+/// they have to come after every required field.
+fn check_optional_fields_are_trailing(fields_info: &[FieldInfo]) {
+    let mut seen_optional = false;
+    for info in fields_info {
+        if info.optional {
+            seen_optional = true;
+        } else if seen_optional {
+            emit_error!(
+                info.span,
+                "required fields cannot come after optional fields"
+            );
+        }
+    }
+}
+
 fn get_parser_for_field(info: &FieldInfo) -> Option<TokenStream2> {
     let var = &info.variable;
     let span = info.span;
     let name = &info.name;
 
     match &info.kind {
-        FieldKind::Payload { ty, size } => Some(quote_spanned! {span=>
-            let #var: #ty = Payload::from_bytes(_b.split_to(#size))?;
+        FieldKind::Payload { ty, .. } => Some(quote_spanned! {span=>
+            let #var: #ty = Payload::from_bytes(_b.split_to(<#ty as PayloadSize>::SIZE))?;
         }),
         FieldKind::Flags { repr } => {
             let get_value = match repr {
@@ -314,10 +855,14 @@ fn get_parser_for_field(info: &FieldInfo) -> Option<TokenStream2> {
                                 kind: FieldKind::Raw {
                                     ty: (*elem_ty).clone(),
                                 },
-                                idx: elem_idx,
-                                span: info.span.clone(),
+                                span: info.span,
                                 variable: format_ident!("{}_{}", &info.variable, elem_idx),
                                 ident: None,
+                                explicit_name: false,
+                                member: info.member.clone(),
+                                optional: false,
+                                range: None,
+                                validate: None,
                             })
                         })
                         .collect::<Vec<_>>();
@@ -343,7 +888,7 @@ fn get_parser_for_field(info: &FieldInfo) -> Option<TokenStream2> {
                 }
                 _ => {
                     emit_error!(ty, ERR_RAW_PRIMITIVE);
-                    return None;
+                    None
                 }
             }
         }
@@ -356,7 +901,7 @@ fn get_serializer_for_field(info: &FieldInfo) -> Option<TokenStream2> {
 
     match &info.kind {
         FieldKind::Payload { .. } => Some(quote_spanned! {span=>
-            _b.put(Payload::to_bytes(&#var));
+            _b.put(Payload::to_bytes(#var));
         }),
         FieldKind::Flags { repr } => {
             let put_value = match repr {
@@ -427,10 +972,14 @@ fn get_serializer_for_field(info: &FieldInfo) -> Option<TokenStream2> {
                                 kind: FieldKind::Raw {
                                     ty: (*elem_ty).clone(),
                                 },
-                                idx: elem_idx,
                                 span: elem_ty.span(),
                                 variable: format_ident!("{}_{}", &info.variable, elem_idx),
                                 ident: None,
+                                explicit_name: false,
+                                member: info.member.clone(),
+                                optional: false,
+                                range: None,
+                                validate: None,
                             })
                         })
                         .collect::<Vec<_>>();
@@ -456,7 +1005,7 @@ fn get_serializer_for_field(info: &FieldInfo) -> Option<TokenStream2> {
                 }
                 _ => {
                     emit_error!(ty, ERR_RAW_PRIMITIVE);
-                    return None;
+                    None
                 }
             }
         }
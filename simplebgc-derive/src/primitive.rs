@@ -22,6 +22,7 @@ pub enum PrimitiveKind {
 
 #[derive(Clone)]
 pub struct InvalidPrimitiveError {
+    #[allow(dead_code)]
     ty: Type,
 }
 
@@ -43,6 +44,20 @@ impl Display for PrimitiveKind {
     }
 }
 
+impl PrimitiveKind {
+    /// Number of bytes this primitive takes up in a payload.
+    pub fn size(&self) -> usize {
+        use PrimitiveKind::*;
+        match self {
+            I8 | U8 | Bool => 1,
+            I16 | U16 => 2,
+            I32 | U32 => 4,
+            I64 | U64 => 8,
+            I128 | U128 => 16,
+        }
+    }
+}
+
 impl IdentFragment for PrimitiveKind {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         Display::fmt(&self, f)
@@ -0,0 +1,436 @@
+//! Backing up the profiles of a controller to a TOML or JSON document, and
+//! restoring them to the same or another controller.
+//!
+//! Every parameter is stored under its spec name (see [`PayloadFields`]), so
+//! a backup can be read and edited by hand:
+//!
+//! ```toml
+//! [[profiles]]
+//! profile_id = 0
+//!
+//! [profiles.params3]
+//! "PID_P[ROLL]" = 10
+//! "GENERAL_FLAGS.EmergencyStop" = false
+//! RC_MAP_YAW = "PWM { source: Yaw }"
+//! ```
+
+use crate::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// Number of profiles stored by the controller.
+pub const PROFILE_COUNT: u8 = 5;
+
+/// Fields of one parameter block, by spec name.
+pub type FieldMap = BTreeMap<String, FieldValue>;
+
+/// The parameters of every profile of a controller.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Backup {
+    pub profiles: Vec<ProfileBackup>,
+}
+
+/// The parameters of one profile. The extended blocks are `None` if the
+/// firmware of the controller does not support them.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProfileBackup {
+    pub profile_id: u8,
+
+    pub params3: FieldMap,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params_ext: Option<FieldMap>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params_ext2: Option<FieldMap>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params_ext3: Option<FieldMap>,
+}
+
+/// A block of parameters that is read and written with its own command.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ParamsBlock {
+    Params3,
+    ParamsExt,
+    ParamsExt2,
+    ParamsExt3,
+}
+
+/// The changes that a restore makes to one block of one profile.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockChanges {
+    pub profile_id: u8,
+    pub block: ParamsBlock,
+    pub changes: Vec<FieldChange>,
+}
+
+#[derive(Error, Clone, Debug, PartialEq)]
+#[error("profile {profile_id}, {block:?}: {error}")]
+pub struct BackupFieldError {
+    pub profile_id: u8,
+    pub block: ParamsBlock,
+    pub error: FieldError,
+}
+
+#[derive(Error, Debug)]
+pub enum BackupError {
+    #[error(transparent)]
+    Gimbal(#[from] GimbalError),
+    #[error("{} fields of the backup are invalid", .0.len())]
+    InvalidFields(Vec<BackupFieldError>),
+    #[error("profile {profile_id}: the controller does not support {block:?}")]
+    UnsupportedBlock { profile_id: u8, block: ParamsBlock },
+    #[error(transparent)]
+    TomlSerialize(#[from] toml::ser::Error),
+    #[error(transparent)]
+    TomlDeserialize(#[from] toml::de::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+/// `PROFILE_ID` decides where a block is written, so it is not part of the
+/// backup.
+const PROFILE_ID: &str = "PROFILE_ID";
+
+fn to_field_map<T: PayloadFields>(params: &T) -> FieldMap {
+    params
+        .fields()
+        .into_iter()
+        .filter(|(name, _)| name != PROFILE_ID)
+        .collect()
+}
+
+/// Treats an error reply as the block not being supported by the firmware.
+fn supported<T>(result: Result<T, GimbalError>) -> Result<Option<T>, GimbalError> {
+    match result {
+        Ok(params) => Ok(Some(params)),
+        Err(GimbalError::Command(_)) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Applies `fields` to a copy of `live`, returning the copy and what changed.
+fn apply<T: PayloadFields + Clone>(
+    live: &T,
+    fields: &FieldMap,
+    profile_id: u8,
+    block: ParamsBlock,
+    errors: &mut Vec<BackupFieldError>,
+) -> (T, BlockChanges) {
+    let mut params = live.clone();
+    let mut pending: Vec<_> = fields
+        .iter()
+        .filter(|(name, _)| *name != PROFILE_ID)
+        .collect();
+
+    // the map is sorted by name, so an optional field may come before the
+    // optional fields that have to be set first; retry those until no more
+    // of them can be set
+    loop {
+        let count = pending.len();
+        let mut missing = Vec::new();
+        for (name, value) in pending {
+            match params.set(name, value) {
+                Ok(()) => {}
+                Err(error @ FieldError::MissingOptional { .. }) => {
+                    missing.push(((name, value), error))
+                }
+                Err(error) => errors.push(BackupFieldError {
+                    profile_id,
+                    block,
+                    error,
+                }),
+            }
+        }
+
+        if missing.is_empty() || missing.len() == count {
+            errors.extend(missing.into_iter().map(|(_, error)| BackupFieldError {
+                profile_id,
+                block,
+                error,
+            }));
+            break;
+        }
+        pending = missing.into_iter().map(|(field, _)| field).collect();
+    }
+
+    if let Err(invalid) = params.validate() {
+        errors.extend(invalid.into_iter().map(|error| BackupFieldError {
+            profile_id,
+            block,
+            error,
+        }));
+    }
+
+    let changes = BlockChanges {
+        profile_id,
+        block,
+        changes: live.diff(&params),
+    };
+    (params, changes)
+}
+
+impl ProfileBackup {
+    /// Reads every parameter block of `profile_id`.
+    pub async fn dump(gimbal: &Gimbal, profile_id: u8) -> Result<ProfileBackup, GimbalError> {
+        Ok(ProfileBackup {
+            profile_id,
+            params3: to_field_map(&gimbal.read_params3(profile_id).await?),
+            params_ext: supported(gimbal.read_params_ext(profile_id).await)?
+                .map(|p| to_field_map(&p)),
+            params_ext2: supported(gimbal.read_params_ext2(profile_id).await)?
+                .map(|p| to_field_map(&p)),
+            params_ext3: supported(gimbal.read_params_ext3(profile_id).await)?
+                .map(|p| to_field_map(&p)),
+        })
+    }
+}
+
+impl Backup {
+    /// Reads every profile of the controller.
+    pub async fn dump(gimbal: &Gimbal) -> Result<Backup, GimbalError> {
+        let mut profiles = Vec::new();
+        for profile_id in 0..PROFILE_COUNT {
+            profiles.push(ProfileBackup::dump(gimbal, profile_id).await?);
+        }
+        Ok(Backup { profiles })
+    }
+
+    pub fn to_toml(&self) -> Result<String, BackupError> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    pub fn from_toml(s: &str) -> Result<Backup, BackupError> {
+        Ok(toml::from_str(s)?)
+    }
+
+    pub fn to_json(&self) -> Result<String, BackupError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(s: &str) -> Result<Backup, BackupError> {
+        Ok(serde_json::from_str(s)?)
+    }
+
+    /// Writes the backup to the controller and returns the fields that differ
+    /// from the live parameters. Fields missing from the backup keep their
+    /// live values, and blocks without changes are not written.
+    ///
+    /// Every field is checked before anything is written, including with
+    /// [`PayloadFields::validate`]; if any of them is unknown or not allowed,
+    /// nothing is written and all of the errors are returned.
+    ///
+    /// If `dry_run` is true, nothing is written at all.
+    pub async fn restore(
+        &self,
+        gimbal: &Gimbal,
+        dry_run: bool,
+    ) -> Result<Vec<BlockChanges>, BackupError> {
+        let mut errors = Vec::new();
+        let mut changes = Vec::new();
+        let mut writes = Vec::new();
+
+        for profile in &self.profiles {
+            let id = profile.profile_id;
+
+            let live = gimbal.read_params3(id).await?;
+            let (mut params, diff) =
+                apply(&live, &profile.params3, id, ParamsBlock::Params3, &mut errors);
+            if !diff.changes.is_empty() {
+                params.profile_id = id;
+                writes.push(OutgoingCommand::WriteParams3(params));
+                changes.push(diff);
+            }
+
+            if let Some(fields) = &profile.params_ext {
+                let block = ParamsBlock::ParamsExt;
+                let live = supported(gimbal.read_params_ext(id).await)?
+                    .ok_or(BackupError::UnsupportedBlock { profile_id: id, block })?;
+                let (mut params, diff) = apply(&live, fields, id, block, &mut errors);
+                if !diff.changes.is_empty() {
+                    params.profile_id = id;
+                    writes.push(OutgoingCommand::WriteParamsExt(params));
+                    changes.push(diff);
+                }
+            }
+
+            if let Some(fields) = &profile.params_ext2 {
+                let block = ParamsBlock::ParamsExt2;
+                let live = supported(gimbal.read_params_ext2(id).await)?
+                    .ok_or(BackupError::UnsupportedBlock { profile_id: id, block })?;
+                let (mut params, diff) = apply(&live, fields, id, block, &mut errors);
+                if !diff.changes.is_empty() {
+                    params.profile_id = id;
+                    writes.push(OutgoingCommand::WriteParamsExt2(params));
+                    changes.push(diff);
+                }
+            }
+
+            if let Some(fields) = &profile.params_ext3 {
+                let block = ParamsBlock::ParamsExt3;
+                let live = supported(gimbal.read_params_ext3(id).await)?
+                    .ok_or(BackupError::UnsupportedBlock { profile_id: id, block })?;
+                let (mut params, diff) = apply(&live, fields, id, block, &mut errors);
+                if !diff.changes.is_empty() {
+                    params.profile_id = id;
+                    writes.push(OutgoingCommand::WriteParamsExt3(params));
+                    changes.push(diff);
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(BackupError::InvalidFields(errors));
+        }
+
+        if !dry_run {
+            for command in writes {
+                gimbal.confirm(command).await?;
+            }
+        }
+
+        Ok(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::tests::{confirm, read_command, write_command};
+    use crate::fields::tests::params3;
+    use crate::*;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, DuplexStream};
+
+    /// Answers a read of profile 1 with default parameters.
+    async fn read_params3(board: &mut DuplexStream) {
+        assert_eq!(
+            read_command(board).await,
+            OutgoingCommand::ReadParams3(ParamsQuery { profile_id: 1 })
+        );
+        write_command(board, IncomingCommand::ReadParams3(params3())).await;
+    }
+
+    fn backup(fields: &[(&str, FieldValue)]) -> Backup {
+        let mut params3 = super::to_field_map(&params3());
+        for (name, value) in fields {
+            params3.insert(name.to_string(), value.clone());
+        }
+
+        Backup {
+            profiles: vec![ProfileBackup {
+                profile_id: 1,
+                params3,
+                params_ext: None,
+                params_ext2: None,
+                params_ext3: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn formats() {
+        let backup = backup(&[("PID_P[ROLL]", FieldValue::Int(10))]);
+        assert!(!backup.profiles[0].params3.contains_key("PROFILE_ID"));
+
+        let toml = backup.to_toml().unwrap();
+        assert!(toml.contains("\"PID_P[ROLL]\" = 10"));
+        assert!(toml.contains("RC_MAP_YAW = \"None\""));
+        assert_eq!(Backup::from_toml(&toml).unwrap(), backup);
+
+        let json = backup.to_json().unwrap();
+        assert_eq!(Backup::from_json(&json).unwrap(), backup);
+    }
+
+    #[tokio::test]
+    async fn restore() {
+        let (io, mut board) = tokio::io::duplex(256);
+        let gimbal = Gimbal::new(io, V1Codec);
+
+        let mut expected = params3();
+        expected.profile_id = 1;
+        expected.pid.roll.p = 10;
+        let board = tokio::spawn(async move {
+            // dry run
+            read_params3(&mut board).await;
+
+            read_params3(&mut board).await;
+            assert_eq!(
+                read_command(&mut board).await,
+                OutgoingCommand::WriteParams3(expected)
+            );
+            confirm(&mut board, 22).await;
+            board
+        });
+
+        let backup = backup(&[("PID_P[ROLL]", FieldValue::Int(10))]);
+        let changes = vec![BlockChanges {
+            profile_id: 1,
+            block: ParamsBlock::Params3,
+            changes: vec![FieldChange {
+                name: "PID_P[ROLL]".into(),
+                old: Some(FieldValue::Int(0)),
+                new: FieldValue::Int(10),
+            }],
+        }];
+        assert_eq!(backup.restore(&gimbal, true).await.unwrap(), changes);
+        assert_eq!(backup.restore(&gimbal, false).await.unwrap(), changes);
+        board.await.unwrap();
+    }
+
+    #[test]
+    fn apply_optional_fields() {
+        let size = <ParamsExt3Data as PayloadSize>::MIN_SIZE;
+        let live = ParamsExt3Data::from_bytes(vec![0; size].into()).unwrap();
+        let block = ParamsBlock::ParamsExt3;
+
+        // sorted by name, these come in the reverse of their payload order
+        let fields: FieldMap = [
+            ("BAT_THRESHOLD_SHUTDOWN", FieldValue::Int(1000)),
+            ("BAT_THRESHOLD_WARN", FieldValue::Int(1100)),
+            ("EULER_ORDER", FieldValue::Enum("PitchRollYaw".into())),
+        ]
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.clone()))
+        .collect();
+        let mut errors = vec![];
+        let (params, diff) = super::apply(&live, &fields, 0, block, &mut errors);
+        assert!(errors.is_empty());
+        assert_eq!(diff.changes.len(), 3);
+        assert_eq!(params.bat_threshold_shutdown, Some(1000));
+
+        // without EULER_ORDER, the battery thresholds would be misaligned
+        let mut fields = fields;
+        fields.remove("EULER_ORDER");
+        let (params, _) = super::apply(&live, &fields, 0, block, &mut errors);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(params, live);
+    }
+
+    #[tokio::test]
+    async fn restore_invalid() {
+        let (io, mut board) = tokio::io::duplex(256);
+        let gimbal = Gimbal::new(io, V1Codec);
+
+        let board = tokio::spawn(async move {
+            read_params3(&mut board).await;
+            board
+        });
+
+        let backup = backup(&[
+            ("PID_P[ROLL]", FieldValue::Int(300)),
+            ("PID_Q[ROLL]", FieldValue::Int(1)),
+        ]);
+        match backup.restore(&gimbal, false).await {
+            Err(BackupError::InvalidFields(errors)) => assert_eq!(errors.len(), 2),
+            other => panic!("expected invalid fields, got {:?}", other),
+        }
+
+        // nothing was written
+        let mut board = board.await.unwrap();
+        let mut buf = [0u8; 1];
+        let read = tokio::time::timeout(Duration::from_millis(10), board.read(&mut buf));
+        assert!(read.await.is_err());
+    }
+}
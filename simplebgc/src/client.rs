@@ -0,0 +1,1663 @@
+//! An asynchronous request/response client for SimpleBGC controllers.
+//!
+//! [`Gimbal`] owns a framed connection to the controller on a background
+//! task. Every request is written to the controller and then matched to the
+//! first incoming command that answers it, either by command id or by a
+//! `CMD_CONFIRM` carrying the request's id. A `CMD_ERROR` is delivered to the
+//! oldest outstanding request, because the controller does not say which
+//! command it refers to. Incoming commands that do not answer a request are
+//! delivered to any [`Subscription`] for their command id.
+
+use crate::commands::constants::*;
+use crate::*;
+use enumflags2::BitFlags;
+use futures::{SinkExt, Stream, StreamExt};
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio_util::codec::{Decoder, Encoder, Framed};
+
+/// How long a request waits for its reply unless another timeout is given.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How often [`Gimbal::calibrate`] asks the controller for its progress.
+pub const CALIB_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long [`Gimbal::auto_pid`] waits for the next progress report before
+/// checking whether tuning is done.
+pub const AUTO_PID_IDLE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Error, Debug)]
+pub enum GimbalError {
+    #[error("timed out waiting for a reply to command {id}")]
+    Timeout { id: u8 },
+    #[error("controller reported error {:?} (code {})", .0.code(), .0.error_code)]
+    Command(ErrorData),
+    #[error("unexpected reply with command id {id}")]
+    UnexpectedReply { id: u8 },
+    #[error(transparent)]
+    Message(#[from] MessageParseError),
+    #[error("the connection to the controller was closed")]
+    Disconnected,
+    #[error("the controller did not report any progress for command {id}")]
+    NoProgress { id: u8 },
+    #[error("refusing to write {} invalid parameters", .0.len())]
+    InvalidParams(Vec<FieldError>),
+}
+
+/// What the controller sends back in response to an outgoing command.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Reply {
+    /// An incoming command with the given id.
+    Data(u8),
+    /// `CMD_CONFIRM` for the given command id.
+    Confirm(u8),
+    /// Nothing; the command is done as soon as it is written.
+    None,
+}
+
+impl Reply {
+    fn matches(&self, msg: &IncomingCommand) -> bool {
+        match (self, msg) {
+            (Reply::Confirm(id), IncomingCommand::CommandConfirm(confirm)) => confirm.cmd_id == *id,
+            (Reply::Confirm(_), _) => false,
+            (Reply::Data(_), IncomingCommand::CommandConfirm(_)) => false,
+            (Reply::Data(id), msg) => msg.command_id() == *id,
+            (Reply::None, _) => false,
+        }
+    }
+}
+
+impl OutgoingCommand {
+    /// Returns the reply the controller sends for this command.
+    pub fn expected_reply(&self) -> Reply {
+        use OutgoingCommand::*;
+        match self {
+            BoardInfo => Reply::Data(CMD_BOARD_INFO),
+            BoardInfo3 => Reply::Data(CMD_BOARD_INFO_3),
+            Reset => Reply::None,
+            Control(_) => Reply::Confirm(CMD_CONTROL),
+            ControlConfig(_) => Reply::Confirm(CMD_CONTROL_CONFIG),
+            MotorsOn => Reply::Confirm(CMD_MOTORS_ON),
+            MotorsOff(_) => Reply::Confirm(CMD_MOTORS_OFF),
+            ReadParams(_) => Reply::Data(CMD_READ_PARAMS),
+            ReadParams3(_) => Reply::Data(CMD_READ_PARAMS_3),
+            ReadParamsExt(_) => Reply::Data(CMD_READ_PARAMS_EXT),
+            ReadParamsExt2(_) => Reply::Data(CMD_READ_PARAMS_EXT2),
+            ReadParamsExt3(_) => Reply::Data(CMD_READ_PARAMS_EXT3),
+            WriteParams(_) => Reply::Confirm(CMD_WRITE_PARAMS),
+            WriteParams3(_) => Reply::Confirm(CMD_WRITE_PARAMS_3),
+            WriteParamsExt(_) => Reply::Confirm(CMD_WRITE_PARAMS_EXT),
+            WriteParamsExt2(_) => Reply::Confirm(CMD_WRITE_PARAMS_EXT2),
+            WriteParamsExt3(_) => Reply::Confirm(CMD_WRITE_PARAMS_EXT3),
+            SaveParams3(_) => Reply::Confirm(CMD_SAVE_PARAMS_3),
+            UseDefaults(_) => Reply::Confirm(CMD_USE_DEFAULTS),
+            RealtimeData3 => Reply::Data(CMD_REALTIME_DATA_3),
+            RealtimeData4 => Reply::Data(CMD_REALTIME_DATA_4),
+            RealtimeDataCustom(_) => Reply::Data(CMD_REALTIME_DATA_CUSTOM),
+            GetAngles => Reply::Data(CMD_GET_ANGLES),
+            GetAnglesExt => Reply::Data(CMD_GET_ANGLES_EXT),
+            DataStreamInterval(_) => Reply::Confirm(CMD_DATA_STREAM_INTERVAL),
+            ExecuteMenu(_) => Reply::Confirm(CMD_EXECUTE_MENU),
+            CalibAcc(_) => Reply::Confirm(CMD_CALIB_ACC),
+            CalibGyro(_) => Reply::Confirm(CMD_CALIB_GYRO),
+            CalibMag(_) => Reply::Confirm(CMD_CALIB_MAG),
+            CalibPoles => Reply::Confirm(CMD_CALIB_POLES),
+            CalibOffset => Reply::Confirm(CMD_CALIB_OFFSET),
+            CalibExtGain => Reply::Confirm(CMD_CALIB_EXT_GAIN),
+            CalibMotorMagLink => Reply::Confirm(CMD_CALIB_MOTOR_MAG_LINK),
+            CalibBat(_) => Reply::Confirm(CMD_CALIB_BAT),
+            CalibAccExtRef(_) => Reply::Confirm(CMD_CALIB_ACC_EXT_REF),
+            CalibOrientCorr(_) => Reply::Confirm(CMD_CALIB_ORIENT_CORR),
+            CalibInfo(_) => Reply::Data(CMD_CALIB_INFO),
+            AutoPid(_) => Reply::Confirm(CMD_AUTO_PID),
+            AutoPid2(_) => Reply::Confirm(CMD_AUTO_PID2),
+            SetAdjVarsVal(_) => Reply::Confirm(CMD_SET_ADJ_VARS_VAL),
+            GetAdjVarsVal(_) => Reply::Data(CMD_SET_ADJ_VARS_VAL),
+            AdjVarsState(_) => Reply::Data(CMD_ADJ_VARS_STATE),
+            ReadAdjVarsCfg => Reply::Data(CMD_READ_ADJ_VARS_CFG),
+            WriteAdjVarsCfg(_) => Reply::Confirm(CMD_WRITE_ADJ_VARS_CFG),
+            ApiVirtChControl(_) => Reply::None,
+            ApiVirtChHighRes(_) => Reply::None,
+            Other { .. } => Reply::None,
+        }
+    }
+}
+
+type ReplySender = oneshot::Sender<Result<Option<IncomingCommand>, GimbalError>>;
+
+struct Request {
+    command: OutgoingCommand,
+    reply: Reply,
+    sender: ReplySender,
+}
+
+struct PendingRequest {
+    reply: Reply,
+    sender: ReplySender,
+}
+
+struct Subscriber {
+    key: u64,
+    cmd_id: StreamCommand,
+    sender: mpsc::UnboundedSender<IncomingCommand>,
+}
+
+enum Action {
+    Request(Request),
+    /// Forwards every incoming command with the given id that does not
+    /// answer a request, until the receiver is dropped.
+    Listen {
+        cmd_id: u8,
+        sender: mpsc::UnboundedSender<IncomingCommand>,
+    },
+    Subscribe(Subscriber),
+    /// Removes a subscriber, disabling its stream if no other subscriber
+    /// needs it. The sender, if any, is answered once that is confirmed.
+    Unsubscribe {
+        key: u64,
+        sender: Option<ReplySender>,
+    },
+}
+
+static NEXT_SUBSCRIPTION_KEY: AtomicU64 = AtomicU64::new(0);
+
+/// A handle to a SimpleBGC controller.
+///
+/// Handles are cheap to clone and can be shared between tasks. The
+/// connection is closed once every handle has been dropped.
+#[derive(Clone, Debug)]
+pub struct Gimbal {
+    actions: mpsc::UnboundedSender<Action>,
+    timeout: Duration,
+    /// Whether the controller confirms `CMD_CONTROL`, as last set through
+    /// [`Gimbal::control_config`]. Shared by every clone of the handle.
+    control_confirm: Arc<AtomicBool>,
+}
+
+macro_rules! expect_reply {
+    ($reply: expr, $variant: ident) => {
+        match $reply {
+            Some(IncomingCommand::$variant(data)) => Ok(data),
+            Some(other) => Err(GimbalError::UnexpectedReply {
+                id: other.command_id(),
+            }),
+            None => Err(GimbalError::Disconnected),
+        }
+    };
+}
+
+impl Gimbal {
+    /// Starts talking to a controller over `io`, framing messages with
+    /// `codec`. This spawns a task, so it must be called from within a Tokio
+    /// runtime.
+    ///
+    /// `codec` is wrapped in a [`ResyncCodec`], so a corrupt frame or a
+    /// command this crate cannot decode is dropped instead of closing the
+    /// connection. Only IO errors end it.
+    pub fn new<T, C>(io: T, codec: C) -> Self
+    where
+        T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        C: Decoder<Item = IncomingCommand, Error = MessageParseError>
+            + Encoder<OutgoingCommand, Error = MessageParseError>
+            + Send
+            + 'static,
+    {
+        let (actions, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(run(Framed::new(io, ResyncCodec::new(codec)), receiver));
+
+        Gimbal {
+            actions,
+            timeout: DEFAULT_TIMEOUT,
+            control_confirm: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    /// Sets the timeout used by every request made through this handle.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Writes `command` to the controller and waits for `reply`, giving up
+    /// after `timeout`. Returns `None` if `reply` is [`Reply::None`].
+    pub async fn execute(
+        &self,
+        command: OutgoingCommand,
+        reply: Reply,
+        timeout: Duration,
+    ) -> Result<Option<IncomingCommand>, GimbalError> {
+        let id = command.command_id();
+        let (sender, receiver) = oneshot::channel();
+
+        self.actions
+            .send(Action::Request(Request {
+                command,
+                reply,
+                sender,
+            }))
+            .map_err(|_| GimbalError::Disconnected)?;
+
+        wait_for_reply(id, receiver, timeout).await
+    }
+
+    /// Writes `command` to the controller and waits for its expected reply.
+    pub async fn request(
+        &self,
+        command: OutgoingCommand,
+    ) -> Result<Option<IncomingCommand>, GimbalError> {
+        let reply = command.expected_reply();
+        self.execute(command, reply, self.timeout).await
+    }
+
+    /// Writes `command` to the controller and waits for `CMD_CONFIRM`.
+    pub async fn confirm(&self, command: OutgoingCommand) -> Result<ConfirmData, GimbalError> {
+        let reply = Reply::Confirm(command.command_id());
+        expect_reply!(
+            self.execute(command, reply, self.timeout).await?,
+            CommandConfirm
+        )
+    }
+
+    /// Writes `command` to the controller without waiting for a reply.
+    pub async fn send(&self, command: OutgoingCommand) -> Result<(), GimbalError> {
+        self.execute(command, Reply::None, self.timeout)
+            .await
+            .map(|_| ())
+    }
+
+    /// Returns every incoming command with id `cmd_id` that does not answer
+    /// a request, until the receiver is dropped.
+    fn listen(&self, cmd_id: u8) -> Result<mpsc::UnboundedReceiver<IncomingCommand>, GimbalError> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.actions
+            .send(Action::Listen { cmd_id, sender })
+            .map_err(|_| GimbalError::Disconnected)?;
+        Ok(receiver)
+    }
+
+    pub async fn board_info(&self) -> Result<BoardInfo, GimbalError> {
+        expect_reply!(self.request(OutgoingCommand::BoardInfo).await?, BoardInfo)
+    }
+
+    pub async fn board_info3(&self) -> Result<BoardInfo3, GimbalError> {
+        expect_reply!(self.request(OutgoingCommand::BoardInfo3).await?, BoardInfo3)
+    }
+
+    pub async fn reset(&self) -> Result<(), GimbalError> {
+        self.send(OutgoingCommand::Reset).await
+    }
+
+    pub async fn motors_on(&self) -> Result<(), GimbalError> {
+        self.request(OutgoingCommand::MotorsOn).await.map(|_| ())
+    }
+
+    pub async fn motors_off(&self, mode: MotorsOffMode) -> Result<(), GimbalError> {
+        self.request(OutgoingCommand::MotorsOff(MotorsOffQuery(mode)))
+            .await
+            .map(|_| ())
+    }
+
+    /// Controls the gimbal. Waits for the controller to confirm the command,
+    /// unless confirmations were disabled with
+    /// [`ControlConfigFlags::NoConfirm`].
+    pub async fn control(&self, data: ControlData) -> Result<(), GimbalError> {
+        if !self.control_confirm.load(Ordering::SeqCst) {
+            return self.send(OutgoingCommand::Control(data)).await;
+        }
+        self.request(OutgoingCommand::Control(data))
+            .await
+            .map(|_| ())
+    }
+
+    /// Changes how the controller handles `CMD_CONTROL` until it is reset.
+    /// Later calls to [`Gimbal::control`] stop waiting for a confirmation if
+    /// `config` sets [`ControlConfigFlags::NoConfirm`].
+    pub async fn control_config(&self, config: ControlConfig) -> Result<(), GimbalError> {
+        self.request(OutgoingCommand::ControlConfig(config)).await?;
+        self.control_confirm.store(
+            !config.flags.contains(ControlConfigFlags::NoConfirm),
+            Ordering::SeqCst,
+        );
+        Ok(())
+    }
+
+    pub async fn read_params(&self, profile_id: u8) -> Result<Params3Data, GimbalError> {
+        expect_reply!(
+            self.request(OutgoingCommand::ReadParams(ParamsQuery { profile_id }))
+                .await?,
+            ReadParams
+        )
+    }
+
+    pub async fn read_params3(&self, profile_id: u8) -> Result<Params3Data, GimbalError> {
+        expect_reply!(
+            self.request(OutgoingCommand::ReadParams3(ParamsQuery { profile_id }))
+                .await?,
+            ReadParams3
+        )
+    }
+
+    pub async fn read_params_ext(&self, profile_id: u8) -> Result<ParamsExtData, GimbalError> {
+        expect_reply!(
+            self.request(OutgoingCommand::ReadParamsExt(ParamsQuery { profile_id }))
+                .await?,
+            ReadParamsExt
+        )
+    }
+
+    pub async fn read_params_ext2(&self, profile_id: u8) -> Result<ParamsExt2Data, GimbalError> {
+        expect_reply!(
+            self.request(OutgoingCommand::ReadParamsExt2(ParamsQuery { profile_id }))
+                .await?,
+            ReadParamsExt2
+        )
+    }
+
+    pub async fn read_params_ext3(&self, profile_id: u8) -> Result<ParamsExt3Data, GimbalError> {
+        expect_reply!(
+            self.request(OutgoingCommand::ReadParamsExt3(ParamsQuery { profile_id }))
+                .await?,
+            ReadParamsExt3
+        )
+    }
+
+    /// Writes the parameters of a profile, after checking them with
+    /// [`PayloadFields::validate`]. Nothing is written if any of them is not
+    /// allowed by the spec.
+    pub async fn write_params3(&self, params: Params3Data) -> Result<(), GimbalError> {
+        params.validate().map_err(GimbalError::InvalidParams)?;
+        self.write_params3_unchecked(params).await
+    }
+
+    /// Writes the parameters of a profile without checking them.
+    pub async fn write_params3_unchecked(&self, params: Params3Data) -> Result<(), GimbalError> {
+        self.request(OutgoingCommand::WriteParams3(params))
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn write_params_ext(&self, params: ParamsExtData) -> Result<(), GimbalError> {
+        self.request(OutgoingCommand::WriteParamsExt(params))
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn write_params_ext2(&self, params: ParamsExt2Data) -> Result<(), GimbalError> {
+        self.request(OutgoingCommand::WriteParamsExt2(params))
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn write_params_ext3(&self, params: ParamsExt3Data) -> Result<(), GimbalError> {
+        self.request(OutgoingCommand::WriteParamsExt3(params))
+            .await
+            .map(|_| ())
+    }
+
+    /// Saves the parameters of a profile, which were written with the
+    /// `write_params*` methods, to the EEPROM.
+    pub async fn save_params3(&self, profile_id: u8) -> Result<(), GimbalError> {
+        self.request(OutgoingCommand::SaveParams3(ParamsQuery { profile_id }))
+            .await
+            .map(|_| ())
+    }
+
+    /// Executes an action of the menu button.
+    pub async fn execute_menu(&self, command: MenuCommand) -> Result<(), GimbalError> {
+        self.request(OutgoingCommand::ExecuteMenu(command))
+            .await
+            .map(|_| ())
+    }
+
+    /// Reads the calibration state of an IMU.
+    pub async fn calib_info(&self, imu: ImuType) -> Result<CalibInfo, GimbalError> {
+        expect_reply!(
+            self.request(OutgoingCommand::CalibInfo(CalibInfoQuery { imu }))
+                .await?,
+            CalibInfo
+        )
+    }
+
+    /// Runs a calibration command, such as [`OutgoingCommand::CalibGyro`],
+    /// reporting each step to `progress`, and gives up after `timeout`.
+    ///
+    /// Once the controller confirms the command, calibrations of the
+    /// accelerometer, gyroscope or magnetometer are followed by polling
+    /// `CMD_CALIB_INFO` every [`CALIB_POLL_INTERVAL`] until they finish. A
+    /// calibration is finished once its progress reaches 100 or drops back to
+    /// 0, or once the calibration time or calibrated flag of the sensor
+    /// changes from what it was before the command was sent. If the
+    /// calibration fails, the controller's error is returned as
+    /// [`GimbalError::Command`].
+    pub async fn calibrate<F>(
+        &self,
+        command: OutgoingCommand,
+        timeout: Duration,
+        mut progress: F,
+    ) -> Result<(), GimbalError>
+    where
+        F: FnMut(CalibEvent),
+    {
+        use OutgoingCommand::*;
+
+        let id = command.command_id();
+        let sensor = match &command {
+            CalibAcc(query) if query.action != CalibAction::Reset => {
+                Some((query.imu, ImuSensorStatus::AccCalibrated))
+            }
+            CalibGyro(query) if query.action != CalibAction::Reset => {
+                Some((query.imu, ImuSensorStatus::GyroCalibrated))
+            }
+            CalibMag(query) if query.action != CalibAction::Reset => {
+                Some((query.imu, ImuSensorStatus::MagCalibrated))
+            }
+            _ => None,
+        };
+
+        let calibration = async {
+            let before = match sensor {
+                Some((imu, _)) => Some(self.calib_info(imu).await?),
+                None => None,
+            };
+
+            let reply = command.expected_reply();
+            expect_reply!(self.execute(command, reply, timeout).await?, CommandConfirm)?;
+            progress(CalibEvent::Started);
+
+            if let (Some((imu, flag)), Some(before)) = (sensor, before) {
+                let mut last = 0;
+                loop {
+                    let info = self.calib_info(imu).await?;
+                    if info.progress != last {
+                        progress(CalibEvent::Progress(info.progress));
+                    }
+                    // progress drops back to 0 once the calibration is done
+                    if info.progress >= 100
+                        || (info.progress == 0 && last > 0)
+                        || calibrated(flag, &before, &info)
+                    {
+                        break;
+                    }
+                    last = info.progress;
+                    tokio::time::sleep(CALIB_POLL_INTERVAL).await;
+                }
+            }
+
+            progress(CalibEvent::Finished);
+            Ok(())
+        };
+
+        tokio::time::timeout(timeout, calibration)
+            .await
+            .unwrap_or(Err(GimbalError::Timeout { id }))
+    }
+
+    /// Runs automatic PID tuning to completion, reporting each progress
+    /// update to `progress`, and returns the tuned PID parameters of the
+    /// profile. Gives up after `timeout`.
+    ///
+    /// Progress reports are always requested. Whenever they stop for
+    /// [`AUTO_PID_IDLE_TIMEOUT`], the PID parameters of the profile are read,
+    /// and tuning is done once they hold the gains of the last report for
+    /// every tuned axis. Fails with [`GimbalError::NoProgress`] if the
+    /// controller never reports any progress.
+    pub async fn auto_pid<F>(
+        &self,
+        mut query: AutoPidQuery,
+        timeout: Duration,
+        mut progress: F,
+    ) -> Result<RollPitchYaw<AxisPidParams>, GimbalError>
+    where
+        F: FnMut(&AutoPidProgress),
+    {
+        query.flags |= AutoPidFlags::SendGui;
+        let profile_id = query.profile_id;
+        let flags = query.flags;
+
+        let tuning = async {
+            let mut reports = self.listen(CMD_AUTO_PID)?;
+            self.confirm(OutgoingCommand::AutoPid(query)).await?;
+
+            let mut last = None;
+            loop {
+                match tokio::time::timeout(AUTO_PID_IDLE_TIMEOUT, reports.recv()).await {
+                    Ok(Some(IncomingCommand::AutoPid(report))) => {
+                        progress(&report);
+                        last = Some(report);
+                    }
+                    Ok(Some(_)) => {}
+                    Ok(None) => return Err(GimbalError::Disconnected),
+                    Err(_) => {
+                        let report = last.ok_or(GimbalError::NoProgress { id: CMD_AUTO_PID })?;
+                        let pid = self.read_params3(profile_id).await?.pid;
+                        if tuned(flags, &report, &pid) {
+                            return Ok(pid);
+                        }
+                    }
+                }
+            }
+        };
+
+        tokio::time::timeout(timeout, tuning)
+            .await
+            .unwrap_or(Err(GimbalError::Timeout { id: CMD_AUTO_PID }))
+    }
+
+    /// Reads the current values of several adjustable variables at once.
+    pub async fn get_adj_vars(&self, vars: &[AdjVar]) -> Result<Vec<AdjVarValue>, GimbalError> {
+        let query = AdjVarsQuery {
+            vars: vars.to_vec(),
+        };
+        expect_reply!(
+            self.request(OutgoingCommand::GetAdjVarsVal(query)).await?,
+            SetAdjVarsVal
+        )
+        .map(|values| values.values)
+    }
+
+    /// Changes the values of several adjustable variables at once. The new
+    /// values are not saved to the EEPROM.
+    pub async fn set_adj_vars(&self, values: &[AdjVarValue]) -> Result<(), GimbalError> {
+        let values = AdjVarsValues {
+            values: values.to_vec(),
+        };
+        self.request(OutgoingCommand::SetAdjVarsVal(values))
+            .await
+            .map(|_| ())
+    }
+
+    /// Reads the inputs of a trigger slot and an analog slot of the
+    /// adjustable variables configuration.
+    pub async fn adj_vars_state(
+        &self,
+        trigger_slot: u8,
+        analog_slot: u8,
+    ) -> Result<AdjVarsState, GimbalError> {
+        let query = AdjVarsStateQuery {
+            trigger_slot,
+            analog_slot,
+        };
+        expect_reply!(
+            self.request(OutgoingCommand::AdjVarsState(query)).await?,
+            AdjVarsState
+        )
+    }
+
+    /// Reads the trigger and analog slots of the adjustable variables.
+    pub async fn read_adj_vars_cfg(&self) -> Result<AdjVarsCfg, GimbalError> {
+        expect_reply!(
+            self.request(OutgoingCommand::ReadAdjVarsCfg).await?,
+            ReadAdjVarsCfg
+        )
+    }
+
+    /// Writes the trigger and analog slots of the adjustable variables. They
+    /// are checked first, like in [`Gimbal::write_params3`].
+    pub async fn write_adj_vars_cfg(&self, cfg: AdjVarsCfg) -> Result<(), GimbalError> {
+        cfg.validate().map_err(GimbalError::InvalidParams)?;
+        self.write_adj_vars_cfg_unchecked(cfg).await
+    }
+
+    /// Writes the trigger and analog slots of the adjustable variables
+    /// without checking them.
+    pub async fn write_adj_vars_cfg_unchecked(&self, cfg: AdjVarsCfg) -> Result<(), GimbalError> {
+        self.request(OutgoingCommand::WriteAdjVarsCfg(cfg))
+            .await
+            .map(|_| ())
+    }
+
+    /// Resets the parameters of a profile to their factory defaults.
+    pub async fn use_defaults(&self, profile_id: u8) -> Result<(), GimbalError> {
+        self.request(OutgoingCommand::UseDefaults(ParamsQuery { profile_id }))
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn realtime_data3(&self) -> Result<RealtimeData3, GimbalError> {
+        expect_reply!(
+            self.request(OutgoingCommand::RealtimeData3).await?,
+            RealtimeData3
+        )
+    }
+
+    pub async fn realtime_data4(&self) -> Result<RealtimeData4, GimbalError> {
+        expect_reply!(
+            self.request(OutgoingCommand::RealtimeData4).await?,
+            RealtimeData4
+        )
+    }
+
+    /// Requests the data blocks given by `flags`.
+    pub async fn realtime_data_custom(
+        &self,
+        flags: BitFlags<RealtimeDataCustomFlags>,
+    ) -> Result<RealtimeDataCustom, GimbalError> {
+        let query = RealtimeDataCustomQuery::new(flags);
+        let raw = expect_reply!(
+            self.request(OutgoingCommand::RealtimeDataCustom(query))
+                .await?,
+            RealtimeDataCustom
+        )?;
+        raw.decode(flags)
+            .map_err(|err| GimbalError::Message(err.into()))
+    }
+
+    pub async fn get_angles(&self) -> Result<RollPitchYaw<AngleInfo>, GimbalError> {
+        expect_reply!(self.request(OutgoingCommand::GetAngles).await?, GetAngles)
+    }
+
+    pub async fn get_angles_ext(&self) -> Result<RollPitchYaw<AngleInfoExt>, GimbalError> {
+        expect_reply!(
+            self.request(OutgoingCommand::GetAnglesExt).await?,
+            GetAnglesExt
+        )
+    }
+
+    /// Asks the controller to send a command periodically, as configured by
+    /// `interval`, and returns a stream of the commands it sends.
+    ///
+    /// Streams for the same command share one interval on the controller, so
+    /// the last subscription made decides the interval for all of them.
+    pub async fn subscribe(
+        &self,
+        interval: DataStreamInterval,
+    ) -> Result<Subscription, GimbalError> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let key = NEXT_SUBSCRIPTION_KEY.fetch_add(1, Ordering::Relaxed);
+
+        self.actions
+            .send(Action::Subscribe(Subscriber {
+                key,
+                cmd_id: interval.cmd_id,
+                sender,
+            }))
+            .map_err(|_| GimbalError::Disconnected)?;
+
+        // if this fails, dropping the subscription removes it again
+        let subscription = Subscription {
+            gimbal: self.clone(),
+            key,
+            receiver,
+            active: true,
+        };
+
+        self.confirm(OutgoingCommand::DataStreamInterval(interval))
+            .await?;
+
+        Ok(subscription)
+    }
+
+    /// Starts sending the value of every virtual channel every `interval`,
+    /// and whenever one of them is set, so that the controller does not treat
+    /// them as lost. Every channel starts without signal. This spawns a task,
+    /// which keeps the connection open until the refresher is dropped.
+    pub fn virt_ch_refresher(
+        &self,
+        resolution: VirtChResolution,
+        interval: Duration,
+    ) -> VirtChRefresher {
+        let channels = [None; VIRT_CH_COUNT];
+        let (sender, mut receiver) = watch::channel(channels);
+        let gimbal = self.clone();
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    changed = receiver.changed() => {
+                        // the refresher was dropped
+                        if changed.is_err() {
+                            break;
+                        }
+                    }
+                }
+
+                let channels = *receiver.borrow();
+                let command = match resolution {
+                    VirtChResolution::Normal => {
+                        OutgoingCommand::ApiVirtChControl(VirtChControl { channels })
+                    }
+                    VirtChResolution::HighRes => OutgoingCommand::ApiVirtChHighRes(VirtChHighRes {
+                        channels: channels.to_vec(),
+                    }),
+                };
+                if gimbal.send(command).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        VirtChRefresher { channels, sender }
+    }
+}
+
+/// Which command a [`VirtChRefresher`] sends the virtual channels with.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum VirtChResolution {
+    /// `CMD_API_VIRT_CH_CONTROL`, with values in -500..500.
+    Normal,
+    /// `CMD_API_VIRT_CH_HIGH_RES`, with values in -16384..16384.
+    HighRes,
+}
+
+/// Keeps the virtual channels of the controller up to date, created with
+/// [`Gimbal::virt_ch_refresher`].
+///
+/// Dropping the refresher stops sending the channels. The controller treats
+/// them as lost once it stops receiving them.
+#[derive(Debug)]
+pub struct VirtChRefresher {
+    channels: [Option<i16>; VIRT_CH_COUNT],
+    sender: watch::Sender<[Option<i16>; VIRT_CH_COUNT]>,
+}
+
+impl VirtChRefresher {
+    /// Returns the value of every channel, where `None` means no signal.
+    pub fn channels(&self) -> [Option<i16>; VIRT_CH_COUNT] {
+        self.channels
+    }
+
+    /// Sets the value of a channel, or `None` for no signal, sends it right
+    /// away and returns the previous value. Returns `None` without sending
+    /// anything if `channel` is not less than [`VIRT_CH_COUNT`].
+    pub fn set(&mut self, channel: usize, value: Option<i16>) -> Option<Option<i16>> {
+        let previous = std::mem::replace(self.channels.get_mut(channel)?, value);
+        let _ = self.sender.send(self.channels);
+        Some(previous)
+    }
+
+    /// Sets the value of every channel and sends them right away.
+    pub fn set_all(&mut self, channels: [Option<i16>; VIRT_CH_COUNT]) {
+        self.channels = channels;
+        let _ = self.sender.send(self.channels);
+    }
+}
+
+/// A stream of commands that the controller sends periodically, created with
+/// [`Gimbal::subscribe`].
+///
+/// Dropping a subscription stops the controller from sending the command
+/// without waiting for it to confirm; use [`Subscription::unsubscribe`] to
+/// wait.
+#[derive(Debug)]
+pub struct Subscription {
+    gimbal: Gimbal,
+    key: u64,
+    receiver: mpsc::UnboundedReceiver<IncomingCommand>,
+    active: bool,
+}
+
+impl Subscription {
+    /// Stops the controller from sending the command, unless another
+    /// subscription still needs it, and waits for confirmation.
+    pub async fn unsubscribe(mut self) -> Result<(), GimbalError> {
+        self.active = false;
+
+        let (sender, receiver) = oneshot::channel();
+        self.gimbal
+            .actions
+            .send(Action::Unsubscribe {
+                key: self.key,
+                sender: Some(sender),
+            })
+            .map_err(|_| GimbalError::Disconnected)?;
+
+        wait_for_reply(CMD_DATA_STREAM_INTERVAL, receiver, self.gimbal.timeout)
+            .await
+            .map(|_| ())
+    }
+}
+
+impl Stream for Subscription {
+    type Item = IncomingCommand;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if self.active {
+            let _ = self.gimbal.actions.send(Action::Unsubscribe {
+                key: self.key,
+                sender: None,
+            });
+        }
+    }
+}
+
+/// Returns true if `info` shows that the sensor given by `flag` was calibrated
+/// since `before` was read, even if the progress was never seen.
+fn calibrated(flag: ImuSensorStatus, before: &CalibInfo, info: &CalibInfo) -> bool {
+    let time_changed = match flag {
+        ImuSensorStatus::AccCalibrated => info.acc_calib_time != before.acc_calib_time,
+        ImuSensorStatus::GyroCalibrated => info.gyro_calib_time != before.gyro_calib_time,
+        _ => false,
+    };
+
+    time_changed || (info.sensor_status.contains(flag) && !before.sensor_status.contains(flag))
+}
+
+/// Returns true if `pid` holds the gains of `report` for every axis tuned
+/// according to `flags`.
+fn tuned(
+    flags: BitFlags<AutoPidFlags>,
+    report: &AutoPidProgress,
+    pid: &RollPitchYaw<AxisPidParams>,
+) -> bool {
+    let axes = [
+        (AutoPidFlags::TuneRoll, report.p.roll, report.i.roll, report.d.roll, &pid.roll),
+        (AutoPidFlags::TunePitch, report.p.pitch, report.i.pitch, report.d.pitch, &pid.pitch),
+        (AutoPidFlags::TuneYaw, report.p.yaw, report.i.yaw, report.d.yaw, &pid.yaw),
+    ];
+
+    axes.iter()
+        .filter(|(flag, ..)| flags.contains(*flag))
+        .all(|&(_, p, i, d, axis)| axis.p == p && axis.i == i && axis.d == d)
+}
+
+async fn wait_for_reply(
+    id: u8,
+    receiver: oneshot::Receiver<Result<Option<IncomingCommand>, GimbalError>>,
+    timeout: Duration,
+) -> Result<Option<IncomingCommand>, GimbalError> {
+    match tokio::time::timeout(timeout, receiver).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(_)) => Err(GimbalError::Disconnected),
+        Err(_) => Err(GimbalError::Timeout { id }),
+    }
+}
+
+async fn run<T, C>(mut framed: Framed<T, C>, mut actions: mpsc::UnboundedReceiver<Action>)
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+    C: Decoder<Item = IncomingCommand, Error = MessageParseError>
+        + Encoder<OutgoingCommand, Error = MessageParseError>,
+{
+    let mut pending: VecDeque<PendingRequest> = VecDeque::new();
+    let mut subscribers: Vec<Subscriber> = Vec::new();
+    let mut listeners: Vec<(u8, mpsc::UnboundedSender<IncomingCommand>)> = Vec::new();
+
+    loop {
+        tokio::select! {
+            action = actions.recv() => {
+                let (command, reply, sender) = match action {
+                    Some(Action::Request(Request { command, reply, sender })) => {
+                        (command, reply, Some(sender))
+                    }
+                    Some(Action::Listen { cmd_id, sender }) => {
+                        listeners.push((cmd_id, sender));
+                        continue;
+                    }
+                    Some(Action::Subscribe(subscriber)) => {
+                        subscribers.push(subscriber);
+                        continue;
+                    }
+                    Some(Action::Unsubscribe { key, sender }) => {
+                        let cmd_id = match subscribers.iter().position(|s| s.key == key) {
+                            Some(idx) => subscribers.remove(idx).cmd_id,
+                            None => continue,
+                        };
+
+                        if subscribers.iter().any(|s| s.cmd_id == cmd_id) {
+                            if let Some(sender) = sender {
+                                let _ = sender.send(Ok(None));
+                            }
+                            continue;
+                        }
+
+                        let command = OutgoingCommand::DataStreamInterval(
+                            DataStreamInterval::disable(cmd_id),
+                        );
+                        let reply = match sender {
+                            Some(_) => command.expected_reply(),
+                            None => Reply::None,
+                        };
+                        (command, reply, sender)
+                    }
+                    // every handle was dropped
+                    None => break,
+                };
+
+                let result = framed.send(command).await;
+                let sender = match sender {
+                    Some(sender) => sender,
+                    None => continue,
+                };
+
+                if let Err(err) = result {
+                    let _ = sender.send(Err(err.into()));
+                } else if reply == Reply::None {
+                    let _ = sender.send(Ok(None));
+                } else {
+                    pending.push_back(PendingRequest { reply, sender });
+                }
+            }
+            msg = framed.next() => {
+                // drop requests whose caller has already timed out
+                pending.retain(|p| !p.sender.is_closed());
+
+                match msg {
+                    Some(Ok(IncomingCommand::CommandError(err))) => {
+                        if let Some(p) = pending.pop_front() {
+                            let _ = p.sender.send(Err(GimbalError::Command(err)));
+                        }
+                    }
+                    Some(Ok(msg)) => {
+                        if let Some(idx) = pending.iter().position(|p| p.reply.matches(&msg)) {
+                            let p = pending.remove(idx).unwrap();
+                            let _ = p.sender.send(Ok(Some(msg)));
+                        } else {
+                            let id = msg.command_id();
+                            subscribers.retain(|s| !s.sender.is_closed());
+                            for s in subscribers.iter().filter(|s| s.cmd_id as u8 == id) {
+                                let _ = s.sender.send(msg.clone());
+                            }
+                            listeners.retain(|(_, sender)| !sender.is_closed());
+                            for (_, sender) in listeners.iter().filter(|(cmd_id, _)| *cmd_id == id) {
+                                let _ = sender.send(msg.clone());
+                            }
+                        }
+                    }
+                    Some(Err(err)) => {
+                        if let Some(p) = pending.pop_front() {
+                            let _ = p.sender.send(Err(err.into()));
+                        }
+                    }
+                    None => break,
+                }
+            }
+        }
+    }
+
+    for p in pending {
+        let _ = p.sender.send(Err(GimbalError::Disconnected));
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::*;
+    use bytes::Bytes;
+    use enumflags2::BitFlags;
+    use futures::StreamExt;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+    pub(crate) async fn read_command(board: &mut DuplexStream) -> OutgoingCommand {
+        let mut buf = vec![0u8; 256];
+        let n = board.read(&mut buf).await.unwrap();
+        OutgoingCommand::from_bytes(&buf[..n]).unwrap().0
+    }
+
+    pub(crate) async fn write_command(board: &mut DuplexStream, cmd: IncomingCommand) {
+        board.write_all(&cmd.to_v1_bytes()).await.unwrap();
+    }
+
+    pub(crate) async fn confirm(board: &mut DuplexStream, cmd_id: u8) {
+        let confirm = ConfirmData { cmd_id, data: None };
+        write_command(board, IncomingCommand::CommandConfirm(confirm)).await;
+    }
+
+    #[tokio::test]
+    async fn request_reply() {
+        let (io, mut board) = tokio::io::duplex(256);
+        let gimbal = Gimbal::new(io, V1Codec);
+
+        let angles = RollPitchYaw {
+            roll: AngleInfo {
+                imu_angle: Angle14(1),
+                target_angle: Angle14(2),
+                target_speed: SpeedUnits(3),
+            },
+            pitch: AngleInfo {
+                imu_angle: Angle14(4),
+                target_angle: Angle14(5),
+                target_speed: SpeedUnits(6),
+            },
+            yaw: AngleInfo {
+                imu_angle: Angle14(7),
+                target_angle: Angle14(8),
+                target_speed: SpeedUnits(9),
+            },
+        };
+
+        let reply = angles;
+        let board = tokio::spawn(async move {
+            assert_eq!(read_command(&mut board).await, OutgoingCommand::GetAngles);
+            write_command(&mut board, IncomingCommand::GetAngles(reply)).await;
+            board
+        });
+
+        assert_eq!(gimbal.get_angles().await.unwrap(), angles);
+        board.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn skip_bad_frames() {
+        let (io, mut board) = tokio::io::duplex(256);
+        let gimbal = Gimbal::new(io, V1Codec);
+
+        let info = AngleInfoExt {
+            imu_angle: Angle14(1),
+            target_angle: Angle14(2),
+            stator_rotor_angle: -70000,
+            reserved: [0; 10],
+        };
+        let angles = RollPitchYaw::from((info, info, info));
+
+        let board = tokio::spawn(async move {
+            assert_eq!(read_command(&mut board).await, OutgoingCommand::GetAnglesExt);
+            // a command this crate cannot decode, then a corrupt byte
+            let unknown = OutgoingCommand::Other { id: 200 }.to_v1_bytes();
+            board.write_all(&unknown).await.unwrap();
+            board.write_all(&[0x3E, 0x00]).await.unwrap();
+            write_command(&mut board, IncomingCommand::GetAnglesExt(angles)).await;
+            board
+        });
+
+        assert_eq!(gimbal.get_angles_ext().await.unwrap(), angles);
+        board.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn execute_menu() {
+        let (io, mut board) = tokio::io::duplex(256);
+        let gimbal = Gimbal::new(io, V1Codec);
+
+        let board = tokio::spawn(async move {
+            assert_eq!(
+                read_command(&mut board).await,
+                OutgoingCommand::ExecuteMenu(MenuCommand::SetCurPosAsHome)
+            );
+            confirm(&mut board, 69).await;
+
+            assert_eq!(
+                read_command(&mut board).await,
+                OutgoingCommand::ExecuteMenu(MenuCommand::SwitchImu)
+            );
+            let err = ErrorData {
+                error_code: 6,
+                error_data: [0; 4],
+            };
+            write_command(&mut board, IncomingCommand::CommandError(err)).await;
+            board
+        });
+
+        gimbal.execute_menu(MenuCommand::SetCurPosAsHome).await.unwrap();
+        assert!(matches!(
+            gimbal.execute_menu(MenuCommand::SwitchImu).await,
+            Err(GimbalError::Command(_))
+        ));
+        board.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn error_reply() {
+        let (io, mut board) = tokio::io::duplex(256);
+        let gimbal = Gimbal::new(io, V1Codec);
+
+        let board = tokio::spawn(async move {
+            assert_eq!(read_command(&mut board).await, OutgoingCommand::MotorsOn);
+            let err = ErrorData {
+                error_code: 6,
+                error_data: [0; 4],
+            };
+            write_command(&mut board, IncomingCommand::CommandError(err)).await;
+            board
+        });
+
+        match gimbal.motors_on().await {
+            Err(GimbalError::Command(err)) => {
+                assert_eq!(err.code(), Some(ErrorCode::UnknownCommand))
+            }
+            other => panic!("expected command error, got {:?}", other),
+        }
+        board.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn timeout() {
+        let (io, _board) = tokio::io::duplex(256);
+        let gimbal = Gimbal::new(io, V1Codec).with_timeout(Duration::from_millis(10));
+
+        match gimbal.board_info().await {
+            Err(GimbalError::Timeout { id }) => assert_eq!(id, 86),
+            other => panic!("expected timeout, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn subscribe() {
+        let (io, mut board) = tokio::io::duplex(256);
+        let gimbal = Gimbal::new(io, V1Codec);
+
+        let angles = |n: i16| {
+            let info = AngleInfo {
+                imu_angle: Angle14(n),
+                target_angle: Angle14(n),
+                target_speed: SpeedUnits(0),
+            };
+            RollPitchYaw::from((info, info, info))
+        };
+
+        let interval = DataStreamInterval::new(StreamCommand::GetAngles, 10);
+        let board = tokio::spawn(async move {
+            assert_eq!(
+                read_command(&mut board).await,
+                OutgoingCommand::DataStreamInterval(interval)
+            );
+            confirm(&mut board, 85).await;
+            for n in 0..3 {
+                write_command(&mut board, IncomingCommand::GetAngles(angles(n))).await;
+            }
+
+            assert_eq!(
+                read_command(&mut board).await,
+                OutgoingCommand::DataStreamInterval(DataStreamInterval::disable(
+                    StreamCommand::GetAngles
+                ))
+            );
+            confirm(&mut board, 85).await;
+            board
+        });
+
+        let mut subscription = gimbal.subscribe(interval).await.unwrap();
+        for n in 0..3 {
+            assert_eq!(
+                subscription.next().await,
+                Some(IncomingCommand::GetAngles(angles(n)))
+            );
+        }
+        subscription.unsubscribe().await.unwrap();
+        board.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn subscribe_streams() {
+        let realtime_data3 = vec![0; <RealtimeData3 as PayloadSize>::SIZE];
+        let realtime_data4 = vec![0; <RealtimeData4 as PayloadSize>::SIZE];
+        let info = AngleInfoExt {
+            imu_angle: Angle14(1),
+            target_angle: Angle14(2),
+            stator_rotor_angle: 3,
+            reserved: [0; 10],
+        };
+
+        let streams = [
+            (
+                StreamCommand::RealtimeData3,
+                IncomingCommand::RealtimeData3(
+                    RealtimeData3::from_bytes(realtime_data3.into()).unwrap(),
+                ),
+            ),
+            (
+                StreamCommand::RealtimeData4,
+                IncomingCommand::RealtimeData4(
+                    RealtimeData4::from_bytes(realtime_data4.into()).unwrap(),
+                ),
+            ),
+            (
+                StreamCommand::RealtimeDataCustom,
+                IncomingCommand::RealtimeDataCustom(RealtimeDataCustomRaw {
+                    timestamp_ms: 1000,
+                    data: vec![1, 0, 2, 0, 3, 0].into(),
+                }),
+            ),
+            (
+                StreamCommand::GetAnglesExt,
+                IncomingCommand::GetAnglesExt(RollPitchYaw::from((info, info, info))),
+            ),
+        ];
+
+        for (cmd_id, msg) in streams {
+            let (io, mut board) = tokio::io::duplex(256);
+            let gimbal = Gimbal::new(io, V1Codec);
+
+            let interval = DataStreamInterval::new(cmd_id, 10);
+            let expected = msg.clone();
+            let board = tokio::spawn(async move {
+                assert_eq!(
+                    read_command(&mut board).await,
+                    OutgoingCommand::DataStreamInterval(interval)
+                );
+                confirm(&mut board, 85).await;
+                write_command(&mut board, expected).await;
+                board
+            });
+
+            let mut subscription = gimbal.subscribe(interval).await.unwrap();
+            assert_eq!(subscription.next().await, Some(msg));
+            board.await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn write_and_save() {
+        let (io, mut board) = tokio::io::duplex(256);
+        let gimbal = Gimbal::new(io, V1Codec);
+
+        let b = vec![0; <ParamsExt3Data as PayloadSize>::SIZE];
+        let params = ParamsExt3Data::from_bytes(b.into()).unwrap();
+        let expected = params.clone();
+        let board = tokio::spawn(async move {
+            assert_eq!(
+                read_command(&mut board).await,
+                OutgoingCommand::WriteParamsExt3(expected)
+            );
+            confirm(&mut board, 105).await;
+
+            assert_eq!(
+                read_command(&mut board).await,
+                OutgoingCommand::SaveParams3(ParamsQuery { profile_id: 255 })
+            );
+            let err = ErrorData {
+                error_code: 2,
+                error_data: [0; 4],
+            };
+            write_command(&mut board, IncomingCommand::CommandError(err)).await;
+            board
+        });
+
+        gimbal.write_params_ext3(params).await.unwrap();
+        match gimbal.save_params3(255).await {
+            Err(GimbalError::Command(err)) => assert_eq!(err.code(), Some(ErrorCode::WrongParams)),
+            other => panic!("expected command error, got {:?}", other),
+        }
+        board.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn refuse_invalid_params() {
+        let (io, mut board) = tokio::io::duplex(256);
+        let gimbal = Gimbal::new(io, V1Codec);
+
+        let mut params = crate::fields::tests::params3();
+        params.rc.roll.rc_lpf = 20;
+        let expected = params.clone();
+        let board = tokio::spawn(async move {
+            // only the unchecked write reaches the board
+            assert_eq!(
+                read_command(&mut board).await,
+                OutgoingCommand::WriteParams3(expected)
+            );
+            confirm(&mut board, 22).await;
+            board
+        });
+
+        match gimbal.write_params3(params.clone()).await {
+            Err(GimbalError::InvalidParams(errors)) => assert_eq!(errors.len(), 1),
+            other => panic!("expected invalid params, got {:?}", other),
+        }
+        gimbal.write_params3_unchecked(params).await.unwrap();
+        board.await.unwrap();
+    }
+
+    /// Calibration state of an idle main IMU that was never calibrated.
+    fn calib_info() -> CalibInfo {
+        CalibInfo {
+            progress: 0,
+            imu: ImuType::Main,
+            acc_data: RollPitchYaw::from((0, 0, 0)),
+            gyro_abs_val: 0,
+            acc_cur_axis: 0,
+            acc_limits_info: 0,
+            imu_temp: 25,
+            temp_calib_gyro_enabled: false,
+            temp_calib_gyro_t_min: 0,
+            temp_calib_gyro_t_max: 0,
+            temp_calib_acc_enabled: false,
+            temp_calib_acc_slot_num: [0; 6],
+            temp_calib_acc_t_min: 0,
+            temp_calib_acc_t_max: 0,
+            h_err_length: 0,
+            acc_calib_time: 0,
+            gyro_calib_time: 0,
+            acc_calib_temp: 0,
+            gyro_calib_temp: 0,
+            imu_model: 0,
+            sensor_status: BitFlags::empty(),
+            calib_quality: RollPitchYaw::from((0, 0, 0)),
+        }
+    }
+
+    #[tokio::test]
+    async fn calibrate() {
+        let (io, mut board) = tokio::io::duplex(256);
+        let gimbal = Gimbal::new(io, V1Codec);
+
+        let info = |progress| CalibInfo {
+            progress,
+            ..calib_info()
+        };
+
+        let query = CalibQuery::new(ImuType::Main, CalibAction::Regular);
+        let board = tokio::spawn(async move {
+            let query_info = OutgoingCommand::CalibInfo(CalibInfoQuery { imu: ImuType::Main });
+
+            // the state before the calibration is read first
+            assert_eq!(read_command(&mut board).await, query_info);
+            write_command(&mut board, IncomingCommand::CalibInfo(info(0))).await;
+            assert_eq!(
+                read_command(&mut board).await,
+                OutgoingCommand::CalibGyro(query)
+            );
+            confirm(&mut board, 103).await;
+
+            for progress in [40, 100] {
+                assert_eq!(read_command(&mut board).await, query_info);
+                write_command(&mut board, IncomingCommand::CalibInfo(info(progress))).await;
+            }
+
+            assert_eq!(read_command(&mut board).await, OutgoingCommand::CalibPoles);
+            let err = ErrorData {
+                error_code: 1,
+                error_data: [0; 4],
+            };
+            write_command(&mut board, IncomingCommand::CommandError(err)).await;
+            board
+        });
+
+        let mut events = vec![];
+        gimbal
+            .calibrate(
+                OutgoingCommand::CalibGyro(query),
+                Duration::from_secs(1),
+                |event| events.push(event),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            events,
+            [
+                CalibEvent::Started,
+                CalibEvent::Progress(40),
+                CalibEvent::Progress(100),
+                CalibEvent::Finished
+            ]
+        );
+
+        let mut events = vec![];
+        match gimbal
+            .calibrate(
+                OutgoingCommand::CalibPoles,
+                Duration::from_secs(1),
+                |event| events.push(event),
+            )
+            .await
+        {
+            Err(GimbalError::Command(err)) => assert_eq!(err.error_code, 1),
+            other => panic!("expected an error, got {:?}", other),
+        }
+        assert!(events.is_empty());
+        board.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn calibrate_without_progress() {
+        let (io, mut board) = tokio::io::duplex(256);
+        let gimbal = Gimbal::new(io, V1Codec);
+
+        let info = |gyro_calib_time, sensor_status| CalibInfo {
+            gyro_calib_time,
+            sensor_status,
+            ..calib_info()
+        };
+
+        let query = CalibQuery::new(ImuType::Main, CalibAction::Regular);
+        let board = tokio::spawn(async move {
+            let query_info = OutgoingCommand::CalibInfo(CalibInfoQuery { imu: ImuType::Main });
+            let old = info(1000, ImuSensorStatus::GyroCalibrated.into());
+
+            assert_eq!(read_command(&mut board).await, query_info);
+            write_command(&mut board, IncomingCommand::CalibInfo(old)).await;
+            assert_eq!(
+                read_command(&mut board).await,
+                OutgoingCommand::CalibGyro(query)
+            );
+            confirm(&mut board, 103).await;
+
+            // the progress is never seen, only the new calibration time
+            assert_eq!(read_command(&mut board).await, query_info);
+            write_command(&mut board, IncomingCommand::CalibInfo(old)).await;
+            assert_eq!(read_command(&mut board).await, query_info);
+            let new = info(2000, ImuSensorStatus::GyroCalibrated.into());
+            write_command(&mut board, IncomingCommand::CalibInfo(new)).await;
+            board
+        });
+
+        let mut events = vec![];
+        gimbal
+            .calibrate(
+                OutgoingCommand::CalibGyro(query),
+                Duration::from_secs(1),
+                |event| events.push(event),
+            )
+            .await
+            .unwrap();
+        assert_eq!(events, [CalibEvent::Started, CalibEvent::Finished]);
+        board.await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn auto_pid() {
+        let (io, mut board) = tokio::io::duplex(256);
+        let gimbal = Gimbal::new(io, V1Codec);
+
+        let report = |iteration| AutoPidProgress {
+            p: RollPitchYaw::from((10, 11, 12)),
+            i: RollPitchYaw::from((20, 21, 22)),
+            d: RollPitchYaw::from((30, 31, 32)),
+            lpf_freq: RollPitchYaw::from((100, 100, 100)),
+            iteration,
+            target_freq: RollPitchYaw::from((40, 40, 40)),
+        };
+
+        let mut query = AutoPidQuery::new(0);
+        query.flags.remove(AutoPidFlags::SendGui);
+        let mut expected = query;
+        expected.flags.insert(AutoPidFlags::SendGui);
+        let tuning = crate::fields::tests::params3();
+        let mut params = tuning.clone();
+        for (axis, gain) in [
+            (&mut params.pid.roll, 0),
+            (&mut params.pid.pitch, 1),
+            (&mut params.pid.yaw, 2),
+        ] {
+            axis.p = 10 + gain;
+            axis.i = 20 + gain;
+            axis.d = 30 + gain;
+        }
+        let tuned = params.pid;
+
+        let board = tokio::spawn(async move {
+            let query_params = OutgoingCommand::ReadParams3(ParamsQuery { profile_id: 0 });
+            assert_eq!(
+                read_command(&mut board).await,
+                OutgoingCommand::AutoPid(expected)
+            );
+            confirm(&mut board, 35).await;
+            write_command(&mut board, IncomingCommand::AutoPid(report(1))).await;
+
+            // a pause in the reports while the gains are still being tuned
+            assert_eq!(read_command(&mut board).await, query_params);
+            write_command(&mut board, IncomingCommand::ReadParams3(tuning)).await;
+
+            write_command(&mut board, IncomingCommand::AutoPid(report(2))).await;
+            assert_eq!(read_command(&mut board).await, query_params);
+            write_command(&mut board, IncomingCommand::ReadParams3(params)).await;
+            board
+        });
+
+        let mut iterations = vec![];
+        let pid = gimbal
+            .auto_pid(query, Duration::from_secs(60), |progress| {
+                iterations.push(progress.iteration)
+            })
+            .await
+            .unwrap();
+        assert_eq!(pid, tuned);
+        assert_eq!(iterations, [1, 2]);
+        board.await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn auto_pid_without_progress() {
+        let (io, mut board) = tokio::io::duplex(256);
+        let gimbal = Gimbal::new(io, V1Codec);
+
+        let board = tokio::spawn(async move {
+            read_command(&mut board).await;
+            confirm(&mut board, 35).await;
+            board
+        });
+
+        let result = gimbal
+            .auto_pid(AutoPidQuery::new(0), Duration::from_secs(60), |_| {})
+            .await;
+        assert!(matches!(result, Err(GimbalError::NoProgress { id: 35 })));
+        board.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn adj_vars() {
+        let (io, mut board) = tokio::io::duplex(256);
+        let gimbal = Gimbal::new(io, V1Codec);
+
+        let value = AdjVarValue {
+            var: AdjVar::DYaw,
+            value: 12,
+        };
+        let board = tokio::spawn(async move {
+            assert_eq!(
+                read_command(&mut board).await,
+                OutgoingCommand::SetAdjVarsVal(AdjVarsValues {
+                    values: vec![value]
+                })
+            );
+            confirm(&mut board, 31).await;
+
+            assert_eq!(
+                read_command(&mut board).await,
+                OutgoingCommand::GetAdjVarsVal(AdjVarsQuery {
+                    vars: vec![AdjVar::DYaw]
+                })
+            );
+            let values = AdjVarsValues {
+                values: vec![value],
+            };
+            write_command(&mut board, IncomingCommand::SetAdjVarsVal(values)).await;
+            board
+        });
+
+        gimbal.set_adj_vars(&[value]).await.unwrap();
+        assert_eq!(gimbal.get_adj_vars(&[AdjVar::DYaw]).await.unwrap(), [value]);
+        board.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn refuse_invalid_adj_vars_cfg() {
+        let (io, mut board) = tokio::io::duplex(256);
+        let gimbal = Gimbal::new(io, V1Codec);
+
+        let mut cfg = AdjVarsCfg::from_bytes(Bytes::from(vec![0; 128])).unwrap();
+        cfg.analog_slots[2].source = RcMap::Serial { channel: 20 };
+        let expected = cfg.clone();
+        let board = tokio::spawn(async move {
+            // only the unchecked write reaches the board
+            assert_eq!(
+                read_command(&mut board).await,
+                OutgoingCommand::WriteAdjVarsCfg(expected)
+            );
+            confirm(&mut board, 44).await;
+            board
+        });
+
+        match gimbal.write_adj_vars_cfg(cfg.clone()).await {
+            Err(GimbalError::InvalidParams(errors)) => assert_eq!(errors.len(), 1),
+            other => panic!("expected invalid params, got {:?}", other),
+        }
+        gimbal.write_adj_vars_cfg_unchecked(cfg).await.unwrap();
+        board.await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn virt_ch_refresher() {
+        let (io, mut board) = tokio::io::duplex(256);
+        let gimbal = Gimbal::new(io, V1Codec);
+
+        let mut channels = [None; VIRT_CH_COUNT];
+        let mut refresher =
+            gimbal.virt_ch_refresher(VirtChResolution::Normal, Duration::from_millis(100));
+        assert_eq!(
+            read_command(&mut board).await,
+            OutgoingCommand::ApiVirtChControl(VirtChControl { channels })
+        );
+
+        assert_eq!(refresher.set(3, Some(250)), Some(None));
+        assert_eq!(refresher.set(VIRT_CH_COUNT, Some(250)), None);
+        channels[3] = Some(250);
+        assert_eq!(refresher.channels(), channels);
+        assert_eq!(
+            read_command(&mut board).await,
+            OutgoingCommand::ApiVirtChControl(VirtChControl { channels })
+        );
+
+        // resent after the interval without any change
+        assert_eq!(
+            read_command(&mut board).await,
+            OutgoingCommand::ApiVirtChControl(VirtChControl { channels })
+        );
+    }
+
+    #[tokio::test]
+    async fn control_no_confirm() {
+        let (io, mut board) = tokio::io::duplex(256);
+        let gimbal = Gimbal::new(io, V1Codec);
+
+        let config = ControlConfig {
+            flags: ControlConfigFlags::NoConfirm.into(),
+            ..ControlConfig::default()
+        };
+        let axis = AxisControlParams {
+            speed: SpeedUnits(0),
+            angle: Angle14(0),
+        };
+        let data = ControlData {
+            mode: ControlFormat::Legacy(AxisControlState {
+                mode: AxisControlMode::Angle,
+                flags: BitFlags::empty(),
+            }),
+            axes: RollPitchYaw::from((axis, axis, axis)),
+        };
+
+        let expected = data.clone();
+        let board = tokio::spawn(async move {
+            assert_eq!(
+                read_command(&mut board).await,
+                OutgoingCommand::ControlConfig(config)
+            );
+            confirm(&mut board, 90).await;
+
+            // no confirm is sent for CMD_CONTROL
+            assert_eq!(
+                read_command(&mut board).await,
+                OutgoingCommand::Control(expected)
+            );
+            board
+        });
+
+        gimbal.control_config(config).await.unwrap();
+        gimbal.clone().control(data).await.unwrap();
+        board.await.unwrap();
+    }
+}
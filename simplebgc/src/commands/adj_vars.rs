@@ -0,0 +1,382 @@
+use crate::payload::ensure_remaining;
+use crate::*;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use num_traits::*;
+use std::convert::TryInto;
+
+/// Parameters that can be read and changed on the fly with
+/// `CMD_GET_ADJ_VARS_VAL` and `CMD_SET_ADJ_VARS_VAL`, or bound to RC inputs
+/// with `CMD_WRITE_ADJ_VARS_CFG`.
+#[derive(FromPrimitive, ToPrimitive, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum AdjVar {
+    PRoll = 0,
+    PPitch = 1,
+    PYaw = 2,
+    IRoll = 3,
+    IPitch = 4,
+    IYaw = 5,
+    DRoll = 6,
+    DPitch = 7,
+    DYaw = 8,
+    PowerRoll = 9,
+    PowerPitch = 10,
+    PowerYaw = 11,
+    AccLimiter = 12,
+    FollowSpeedRoll = 13,
+    FollowSpeedPitch = 14,
+    FollowSpeedYaw = 15,
+    FollowLpfRoll = 16,
+    FollowLpfPitch = 17,
+    FollowLpfYaw = 18,
+    RcSpeedRoll = 19,
+    RcSpeedPitch = 20,
+    RcSpeedYaw = 21,
+    RcLpfRoll = 22,
+    RcLpfPitch = 23,
+    RcLpfYaw = 24,
+    RcTrimRoll = 25,
+    RcTrimPitch = 26,
+    RcTrimYaw = 27,
+    RcDeadband = 28,
+    RcExpoRate = 29,
+    FollowMode = 30,
+    RcFollowYaw = 31,
+    RcFollowPitch = 32,
+    FollowDeadband = 33,
+    FollowExpoRate = 34,
+    FollowRollMixStart = 35,
+    FollowRollMixRange = 36,
+    GyroTrust = 37,
+    FrameHeadingAngle = 38,
+    GyroHeadingCorrection = 39,
+    AccLimiterRoll = 40,
+    AccLimiterPitch = 41,
+    AccLimiterYaw = 42,
+    PidGainRoll = 43,
+    PidGainPitch = 44,
+    PidGainYaw = 45,
+    LpfFreqRoll = 46,
+    LpfFreqPitch = 47,
+    LpfFreqYaw = 48,
+    TimelapseTime = 49,
+    MavCtrlMode = 50,
+    HCorrFactor = 51,
+    SwLimMinRoll = 52,
+    SwLimMaxRoll = 53,
+    SwLimMinPitch = 54,
+    SwLimMaxPitch = 55,
+    SwLimMinYaw = 56,
+    SwLimMaxYaw = 57,
+    FollowRangeRoll = 58,
+    FollowRangePitch = 59,
+    FollowRangeYaw = 60,
+    AutoPidTarget = 61,
+    RcModeRoll = 62,
+    RcModePitch = 63,
+    RcModeYaw = 64,
+    EulerOrder = 65,
+    FollowInDband = 66,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AdjVarValue {
+    pub var: AdjVar,
+    pub value: i32,
+}
+
+/// Values of adjustable variables. Sent with `CMD_SET_ADJ_VARS_VAL` to change
+/// them, and received as `CMD_SET_ADJ_VARS_VAL` in reply to
+/// `CMD_GET_ADJ_VARS_VAL`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AdjVarsValues {
+    pub values: Vec<AdjVarValue>,
+}
+
+impl Payload for AdjVarsValues {
+    fn from_bytes(mut b: Bytes) -> Result<Self, PayloadParseError>
+    where
+        Self: Sized,
+    {
+        ensure_remaining(&b, "NUM_PARAMS", 1)?;
+        let count = b.get_u8() as usize;
+        ensure_remaining(&b, "PARAM", count * 5)?;
+
+        let values = (0..count)
+            .map(|_| {
+                Ok(AdjVarValue {
+                    var: read_enum!(b, "PARAM_ID", u8)?,
+                    value: b.get_i32_le(),
+                })
+            })
+            .collect::<Result<_, PayloadParseError>>()?;
+
+        Ok(AdjVarsValues { values })
+    }
+
+    fn to_bytes(&self) -> Bytes
+    where
+        Self: Sized,
+    {
+        let mut b = BytesMut::with_capacity(1 + self.values.len() * 5);
+        b.put_u8(self.values.len() as u8);
+        for value in &self.values {
+            b.put_u8(value.var.to_u8().unwrap());
+            b.put_i32_le(value.value);
+        }
+        b.freeze()
+    }
+}
+
+/// Requests the values of adjustable variables.
+/// (frw. ver. 2.62b5+)
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AdjVarsQuery {
+    pub vars: Vec<AdjVar>,
+}
+
+impl Payload for AdjVarsQuery {
+    fn from_bytes(mut b: Bytes) -> Result<Self, PayloadParseError>
+    where
+        Self: Sized,
+    {
+        ensure_remaining(&b, "NUM_PARAMS", 1)?;
+        let count = b.get_u8() as usize;
+        ensure_remaining(&b, "PARAM_ID", count)?;
+
+        let vars = (0..count)
+            .map(|_| read_enum!(b, "PARAM_ID", u8))
+            .collect::<Result<_, PayloadParseError>>()?;
+
+        Ok(AdjVarsQuery { vars })
+    }
+
+    fn to_bytes(&self) -> Bytes
+    where
+        Self: Sized,
+    {
+        let mut b = BytesMut::with_capacity(1 + self.vars.len());
+        b.put_u8(self.vars.len() as u8);
+        for var in &self.vars {
+            b.put_u8(var.to_u8().unwrap());
+        }
+        b.freeze()
+    }
+}
+
+/// Requests the state of a trigger slot and an analog slot of the adjustable
+/// variables configuration.
+/// (frw. ver. 2.62+)
+#[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AdjVarsStateQuery {
+    #[kind(raw)]
+    #[name("TRIGGER_SLOT")]
+    pub trigger_slot: u8,
+
+    #[kind(raw)]
+    #[name("ANALOG_SLOT")]
+    pub analog_slot: u8,
+}
+
+#[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[size(15)]
+pub struct AdjVarsState {
+    /// Signal on the input of the trigger slot
+    /// Units: -500..500
+    #[kind(raw)]
+    #[name("TRIGGER_RC_DATA")]
+    pub trigger_rc_data: i16,
+
+    /// Action assigned to the current position of the trigger slot
+    #[kind(enumeration)]
+    #[format(u8)]
+    #[name("TRIGGER_ACTION")]
+    pub trigger_action: MenuCommand,
+
+    /// Signal on the input of the analog slot
+    /// Units: -500..500
+    #[kind(raw)]
+    #[name("ANALOG_RC_DATA")]
+    pub analog_rc_data: i16,
+
+    /// Current value of the variable assigned to the analog slot
+    #[kind(raw)]
+    #[name("ANALOG_VALUE")]
+    pub analog_value: i32,
+
+    #[kind(raw)]
+    #[name("RESERVED")]
+    pub reserved: [u8; 6],
+}
+
+/// Runs menu commands when an RC input moves between positions.
+#[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TriggerSlot {
+    /// Input that triggers the actions
+    #[kind(enumeration)]
+    #[format(u8)]
+    #[name("TRIGGER_SRC_CH")]
+    #[validate(RcMap::is_valid)]
+    pub source: RcMap,
+
+    /// Executed when the input enters the lowest of its 5 positions
+    #[kind(enumeration)]
+    #[format(u8)]
+    #[name("TRIGGER_ACTION1")]
+    pub action_1: MenuCommand,
+
+    #[kind(enumeration)]
+    #[format(u8)]
+    #[name("TRIGGER_ACTION2")]
+    pub action_2: MenuCommand,
+
+    #[kind(enumeration)]
+    #[format(u8)]
+    #[name("TRIGGER_ACTION3")]
+    pub action_3: MenuCommand,
+
+    #[kind(enumeration)]
+    #[format(u8)]
+    #[name("TRIGGER_ACTION4")]
+    pub action_4: MenuCommand,
+
+    /// Executed when the input enters the highest of its 5 positions
+    #[kind(enumeration)]
+    #[format(u8)]
+    #[name("TRIGGER_ACTION5")]
+    pub action_5: MenuCommand,
+}
+
+/// Controls an adjustable variable with an RC input.
+#[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnalogSlot {
+    /// Input that controls the variable, or `RcMap::None` if the slot is
+    /// unused
+    #[kind(enumeration)]
+    #[format(u8)]
+    #[name("ANALOG_SRC")]
+    #[validate(RcMap::is_valid)]
+    pub source: RcMap,
+
+    #[kind(enumeration)]
+    #[format(u8)]
+    #[name("VAR_ID")]
+    pub var: AdjVar,
+
+    /// Value at the low end of the input, scaled from 0 to 255 over the
+    /// range of the variable
+    #[kind(raw)]
+    #[name("MIN_VAL")]
+    pub min_val: u8,
+
+    /// Value at the high end of the input, scaled from 0 to 255 over the
+    /// range of the variable
+    #[kind(raw)]
+    #[name("MAX_VAL")]
+    pub max_val: u8,
+}
+
+pub const TRIGGER_SLOT_COUNT: usize = 10;
+pub const ANALOG_SLOT_COUNT: usize = 15;
+
+/// Configuration of the adjustable variables, read with
+/// `CMD_READ_ADJ_VARS_CFG` and written with `CMD_WRITE_ADJ_VARS_CFG`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AdjVarsCfg {
+    pub trigger_slots: [TriggerSlot; TRIGGER_SLOT_COUNT],
+    pub analog_slots: [AnalogSlot; ANALOG_SLOT_COUNT],
+    pub reserved: [u8; 8],
+}
+
+impl Payload for AdjVarsCfg {
+    fn from_bytes(mut b: Bytes) -> Result<Self, PayloadParseError>
+    where
+        Self: Sized,
+    {
+        ensure_remaining(&b, "ADJ_VARS_CFG", <Self as PayloadSize>::SIZE)?;
+
+        let trigger_slots = (0..TRIGGER_SLOT_COUNT)
+            .map(|_| Payload::from_bytes(b.split_to(TriggerSlot::SIZE)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let analog_slots = (0..ANALOG_SLOT_COUNT)
+            .map(|_| Payload::from_bytes(b.split_to(AnalogSlot::SIZE)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut reserved = [0; 8];
+        b.copy_to_slice(&mut reserved);
+
+        Ok(AdjVarsCfg {
+            trigger_slots: trigger_slots.try_into().unwrap(),
+            analog_slots: analog_slots.try_into().unwrap(),
+            reserved,
+        })
+    }
+
+    fn to_bytes(&self) -> Bytes
+    where
+        Self: Sized,
+    {
+        let mut b = BytesMut::with_capacity(<Self as PayloadSize>::SIZE);
+        for slot in &self.trigger_slots {
+            b.put(slot.to_bytes());
+        }
+        for slot in &self.analog_slots {
+            b.put(slot.to_bytes());
+        }
+        b.put_slice(&self.reserved);
+        b.freeze()
+    }
+}
+
+impl PayloadSize for AdjVarsCfg {
+    const SIZE: usize =
+        TRIGGER_SLOT_COUNT * TriggerSlot::SIZE + ANALOG_SLOT_COUNT * AnalogSlot::SIZE + 8;
+}
+
+impl PayloadFields for AdjVarsCfg {
+    fn visit_fields(&self, path: &FieldPath, visit: &mut dyn FnMut(String, FieldValue)) {
+        for (i, slot) in self.trigger_slots.iter().enumerate() {
+            slot.visit_fields(&path.index(&i.to_string()), visit);
+        }
+        for (i, slot) in self.analog_slots.iter().enumerate() {
+            slot.visit_fields(&path.index(&i.to_string()), visit);
+        }
+    }
+
+    fn set_field(
+        &mut self,
+        path: &FieldPath,
+        name: &str,
+        value: &FieldValue,
+    ) -> Result<bool, FieldError> {
+        for (i, slot) in self.trigger_slots.iter_mut().enumerate() {
+            if slot.set_field(&path.index(&i.to_string()), name, value)? {
+                return Ok(true);
+            }
+        }
+        for (i, slot) in self.analog_slots.iter_mut().enumerate() {
+            if slot.set_field(&path.index(&i.to_string()), name, value)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn validate_fields(&self, path: &FieldPath, errors: &mut Vec<FieldError>) {
+        for (i, slot) in self.trigger_slots.iter().enumerate() {
+            slot.validate_fields(&path.index(&i.to_string()), errors);
+        }
+        for (i, slot) in self.analog_slots.iter().enumerate() {
+            slot.validate_fields(&path.index(&i.to_string()), errors);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use crate::*;
+use bytes::{BufMut, Bytes, BytesMut};
+use enumflags2::{bitflags, BitFlags};
+
+#[bitflags]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum AutoPidFlags {
+    TuneRoll = 1 << 0,
+    TunePitch = 1 << 1,
+    TuneYaw = 1 << 2,
+    /// Send the progress of the tuning back as `CMD_AUTO_PID`
+    SendGui = 1 << 3,
+    /// Start from the current PID values instead of from scratch
+    KeepCurrent = 1 << 4,
+    TuneLpf = 1 << 5,
+    /// Write the result to every profile
+    AllProfiles = 1 << 6,
+}
+
+/// Starts automatic tuning of the PID controller.
+#[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[size(19)]
+pub struct AutoPidQuery {
+    /// Profile to tune, or 255 for the current profile
+    #[kind(raw)]
+    #[name("PROFILE_ID")]
+    pub profile_id: u8,
+
+    #[kind(flags)]
+    #[format(u8)]
+    #[name("CFG_FLAGS")]
+    #[cfg_attr(feature = "serde", serde(with = "crate::flag_names"))]
+    pub flags: BitFlags<AutoPidFlags>,
+
+    /// From 0 (favour stability) to 255 (favour high gains)
+    #[kind(raw)]
+    #[name("GAIN_VS_STABILITY")]
+    pub gain_vs_stability: u8,
+
+    /// Momentum of the payload, or 0 to estimate it automatically
+    #[kind(raw)]
+    #[name("MOMENTUM")]
+    pub momentum: u8,
+
+    #[kind(raw)]
+    #[name("RESERVED")]
+    pub reserved: [u8; 15],
+}
+
+impl AutoPidQuery {
+    /// Tunes every axis of `profile_id` with a balanced gain, estimating the
+    /// momentum and reporting progress.
+    pub fn new(profile_id: u8) -> Self {
+        AutoPidQuery {
+            profile_id,
+            flags: AutoPidFlags::TuneRoll
+                | AutoPidFlags::TunePitch
+                | AutoPidFlags::TuneYaw
+                | AutoPidFlags::SendGui,
+            gain_vs_stability: 128,
+            momentum: 0,
+            reserved: [0; 15],
+        }
+    }
+}
+
+#[derive(FromPrimitive, ToPrimitive, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum AutoPid2Action {
+    /// Start tuning with the given configuration
+    Start = 1,
+    /// Start tuning, and save the configuration as the default
+    StartAndSave = 2,
+    /// Only save the configuration as the default
+    SaveConfig = 3,
+}
+
+#[bitflags]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u16)]
+pub enum AutoPid2Flags {
+    /// Start from the current PID values instead of from scratch
+    KeepCurrent = 1 << 0,
+    /// Write the result to every profile
+    AllProfiles = 1 << 1,
+    TuneLpf = 1 << 2,
+    /// Send the progress of the tuning back as `CMD_AUTO_PID`
+    SendGui = 1 << 3,
+}
+
+#[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[size(13)]
+pub struct AutoPid2AxisConfig {
+    #[kind(raw)]
+    #[format(u8)]
+    #[name("AXIS_ENABLED")]
+    pub enabled: bool,
+
+    /// From 0 (favour stability) to 255 (favour high gains)
+    #[kind(raw)]
+    #[name("GAIN")]
+    pub gain: u8,
+
+    /// Amplitude of the test signal
+    #[kind(raw)]
+    #[name("STIMULUS")]
+    pub stimulus: u16,
+
+    /// Frequency the tuning aims for
+    /// Units: 1 Hz
+    #[kind(raw)]
+    #[name("EFFECTIVE_FREQ")]
+    pub effective_freq: u8,
+
+    /// Frequency of a known resonance, or 0 if there is none
+    /// Units: 1 Hz
+    #[kind(raw)]
+    #[name("PROBLEM_FREQ")]
+    pub problem_freq: u8,
+
+    /// How far to keep away from PROBLEM_FREQ
+    #[kind(raw)]
+    #[name("PROBLEM_MARGIN")]
+    pub problem_margin: u8,
+
+    #[kind(raw)]
+    #[name("RESERVED")]
+    pub reserved: [u8; 6],
+}
+
+payload_rpy!(AutoPid2AxisConfig);
+
+/// Starts or configures the second version of automatic PID tuning.
+/// (frw. ver. 2.70+)
+#[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[size(77)]
+pub struct AutoPid2Query {
+    #[kind(enumeration)]
+    #[format(u8)]
+    #[name("ACTION")]
+    pub action: AutoPid2Action,
+
+    #[kind(raw)]
+    #[name("RESERVED1")]
+    pub reserved1: [u8; 10],
+
+    #[kind(raw)]
+    #[name("CFG_VERSION")]
+    pub cfg_version: u8,
+
+    #[kind(payload)]
+    #[name("AXIS")]
+    pub axes: RollPitchYaw<AutoPid2AxisConfig>,
+
+    #[kind(flags)]
+    #[format(u16)]
+    #[name("GENERAL_FLAGS")]
+    #[cfg_attr(feature = "serde", serde(with = "crate::flag_names"))]
+    pub flags: BitFlags<AutoPid2Flags>,
+
+    #[kind(raw)]
+    #[name("STARTUP_CFG")]
+    pub startup_cfg: u16,
+
+    #[kind(raw)]
+    #[name("RESERVED2")]
+    pub reserved2: [u8; 22],
+}
+
+/// Progress of automatic PID tuning, sent as `CMD_AUTO_PID` while tuning if
+/// requested with [`AutoPidFlags::SendGui`].
+#[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AutoPidProgress {
+    #[kind(payload)]
+    #[name("P")]
+    pub p: RollPitchYaw<u8>,
+
+    #[kind(payload)]
+    #[name("I")]
+    pub i: RollPitchYaw<u8>,
+
+    #[kind(payload)]
+    #[name("D")]
+    pub d: RollPitchYaw<u8>,
+
+    /// Units: 1 Hz
+    #[kind(payload)]
+    #[name("LPF_FREQ")]
+    pub lpf_freq: RollPitchYaw<u16>,
+
+    #[kind(raw)]
+    #[name("ITERATION_CNT")]
+    pub iteration: u16,
+
+    /// Frequency the tuning is currently aiming for
+    /// Units: 1 Hz
+    #[kind(payload)]
+    #[name("TARGET_FREQ")]
+    pub target_freq: RollPitchYaw<u16>,
+}
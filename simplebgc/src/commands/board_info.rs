@@ -1,8 +1,11 @@
-use crate::{Payload, PayloadParseError};
+use crate::{
+    FieldError, FieldPath, FieldValue, Payload, PayloadFields, PayloadParseError, PayloadSize,
+};
 use enumflags2::{BitFlags, bitflags};
 
 #[bitflags]
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum StateFlags1 {
     DebugMode = 1 << 0,
@@ -14,6 +17,7 @@ pub enum StateFlags1 {
 
 #[bitflags]
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u16)]
 pub enum BoardFeatures {
     ThreeAxis = 1 << 0,
@@ -36,12 +40,14 @@ pub enum BoardFeatures {
 
 #[bitflags]
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum ConnectionFlag {
     USB = 1 << 0,
 }
 
 #[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BoardInfo {
     #[kind(raw)]
     #[name("BOARD_VER")]
@@ -54,24 +60,41 @@ pub struct BoardInfo {
     #[kind(flags)]
     #[name("STATE_FLAGS1")]
     #[format(u8)]
+    #[cfg_attr(feature = "serde", serde(with = "crate::flag_names"))]
     pub state: BitFlags<StateFlags1>,
 
     #[kind(flags)]
     #[format(u16)]
+    #[cfg_attr(feature = "serde", serde(with = "crate::flag_names"))]
     pub board_features: BitFlags<BoardFeatures>,
 
     #[kind(flags)]
     #[format(u8)]
+    #[cfg_attr(feature = "serde", serde(with = "crate::flag_names"))]
     pub connection_flag: BitFlags<ConnectionFlag>,
 
     #[kind(raw)]
     pub frw_extra_id: u32,
 
+    /// Not sent by older firmware
     #[kind(raw)]
-    pub reserved: [u8; 7],
+    #[optional]
+    pub board_features_ext: Option<u16>,
+
+    #[kind(raw)]
+    #[optional]
+    pub reserved: Option<[u8; 3]>,
+
+    /// Version of the base firmware for customized builds.
+    /// Not sent by older firmware
+    #[kind(raw)]
+    #[name("BASE_FRW_VER")]
+    #[optional]
+    pub base_firmware_version: Option<u16>,
 }
 
 #[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BoardInfo3 {
     #[kind(raw)]
     pub device_id: [u8; 9],
@@ -0,0 +1,261 @@
+use crate::*;
+use enumflags2::{bitflags, BitFlags};
+
+#[derive(FromPrimitive, ToPrimitive, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum CalibAction {
+    /// Run the regular calibration
+    Regular = 1,
+    /// Reset every calibration of the IMU
+    Reset = 2,
+    /// Run the extended calibration, which is only supported by
+    /// `CMD_CALIB_ACC`
+    Extended = 3,
+}
+
+/// Extended format of `CMD_CALIB_ACC`, `CMD_CALIB_GYRO` and `CMD_CALIB_MAG`.
+/// (frw. ver. 2.60+)
+#[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[size(12)]
+pub struct CalibQuery {
+    /// IMU to calibrate
+    #[kind(enumeration)]
+    #[format(u8)]
+    #[name("IMU_IDX")]
+    pub imu: ImuType,
+
+    #[kind(enumeration)]
+    #[format(u8)]
+    #[name("ACTION")]
+    pub action: CalibAction,
+
+    /// How long to collect data for, or 0 for the default
+    /// Units: ms
+    #[kind(raw)]
+    #[name("TIME_MS")]
+    pub time_ms: u16,
+
+    #[kind(raw)]
+    #[name("RESERVED")]
+    pub reserved: [u8; 8],
+}
+
+impl CalibQuery {
+    /// Runs `action` on `imu` for the default time.
+    pub fn new(imu: ImuType, action: CalibAction) -> Self {
+        CalibQuery {
+            imu,
+            action,
+            time_ms: 0,
+            reserved: [0; 8],
+        }
+    }
+}
+
+/// Calibrates the battery voltage sensor.
+#[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CalibBatQuery {
+    /// Measured voltage of the battery
+    /// Units: 0.01 volt
+    #[kind(raw)]
+    #[name("ACTUAL_VOLTAGE")]
+    pub actual_voltage: u16,
+}
+
+/// Refines the accelerometer calibration of the main IMU, using the direction
+/// of gravity measured by an external reference.
+/// (frw. ver. 2.68+)
+#[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[size(13)]
+pub struct CalibAccExtRefQuery {
+    /// Gravity vector in the coordinates of the IMU
+    /// Units: 1/512 G
+    #[kind(payload)]
+    #[name("ACC_REF")]
+    pub acc_ref: RollPitchYaw<i16>,
+
+    #[kind(raw)]
+    #[name("RESERVED")]
+    pub reserved: [u8; 7],
+}
+
+/// Starts calibrating the correction of the IMU mounting angles.
+/// (frw. ver. 2.68+)
+#[derive(BgcPayload, Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[size(16)]
+pub struct CalibOrientCorrQuery {
+    #[kind(raw)]
+    #[name("RESERVED")]
+    pub reserved: [u8; 16],
+}
+
+/// Requests `CMD_CALIB_INFO` for an IMU.
+#[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CalibInfoQuery {
+    #[kind(enumeration)]
+    #[format(u8)]
+    #[name("IMU_TYPE")]
+    pub imu: ImuType,
+}
+
+#[bitflags]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum ImuSensorStatus {
+    AccPresent = 1 << 0,
+    GyroPresent = 1 << 1,
+    MagPresent = 1 << 2,
+    AccCalibrated = 1 << 3,
+    GyroCalibrated = 1 << 4,
+    MagCalibrated = 1 << 5,
+    /// The IMU reported an error since it was powered on
+    Error = 1 << 7,
+}
+
+/// Calibration state of an IMU, sent in reply to `CMD_CALIB_INFO`.
+#[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CalibInfo {
+    /// Progress of the running calibration, or 0 if none is running
+    /// Units: 1 %
+    #[kind(raw)]
+    #[name("PROGRESS")]
+    pub progress: u8,
+
+    #[kind(enumeration)]
+    #[format(u8)]
+    #[name("IMU_TYPE")]
+    pub imu: ImuType,
+
+    /// Raw accelerometer data
+    /// Units: 1/512 G
+    #[kind(payload)]
+    #[name("ACC_DATA")]
+    pub acc_data: RollPitchYaw<i16>,
+
+    /// Magnitude of the raw gyroscope data, which should be close to 0 while
+    /// the IMU is still
+    #[kind(raw)]
+    #[name("GYRO_ABS_VAL")]
+    pub gyro_abs_val: u16,
+
+    /// Axis currently being calibrated by the extended accelerometer
+    /// calibration
+    #[kind(raw)]
+    #[name("ACC_CUR_AXIS")]
+    pub acc_cur_axis: u8,
+
+    /// Which axis limits have been reached by the extended accelerometer
+    /// calibration, one bit per limit
+    #[kind(raw)]
+    #[name("ACC_LIMITS_INFO")]
+    pub acc_limits_info: u8,
+
+    /// Units: 1 degree Celsius
+    #[kind(raw)]
+    #[name("IMU_TEMP_CELSIUS")]
+    pub imu_temp: i8,
+
+    #[kind(raw)]
+    #[format(u8)]
+    #[name("TEMP_CALIB_GYRO_ENABLED")]
+    pub temp_calib_gyro_enabled: bool,
+
+    /// Units: 1 degree Celsius
+    #[kind(raw)]
+    #[name("TEMP_CALIB_GYRO_T_MIN_CELSIUS")]
+    pub temp_calib_gyro_t_min: i8,
+
+    /// Units: 1 degree Celsius
+    #[kind(raw)]
+    #[name("TEMP_CALIB_GYRO_T_MAX_CELSIUS")]
+    pub temp_calib_gyro_t_max: i8,
+
+    #[kind(raw)]
+    #[format(u8)]
+    #[name("TEMP_CALIB_ACC_ENABLED")]
+    pub temp_calib_acc_enabled: bool,
+
+    /// Number of calibrated temperature slots for each of the 6 accelerometer
+    /// positions
+    #[kind(raw)]
+    #[name("TEMP_CALIB_ACC_SLOT_NUM")]
+    pub temp_calib_acc_slot_num: [u8; 6],
+
+    /// Units: 1 degree Celsius
+    #[kind(raw)]
+    #[name("TEMP_CALIB_ACC_T_MIN_CELSIUS")]
+    pub temp_calib_acc_t_min: i8,
+
+    /// Units: 1 degree Celsius
+    #[kind(raw)]
+    #[name("TEMP_CALIB_ACC_T_MAX_CELSIUS")]
+    pub temp_calib_acc_t_max: i8,
+
+    /// Length of the error vector of the magnetometer calibration
+    #[kind(raw)]
+    #[name("H_ERR_LENGTH")]
+    pub h_err_length: u8,
+
+    /// Time of the last accelerometer calibration, or 0 if it was never
+    /// calibrated
+    /// Units: seconds since the Unix epoch, as set by the GUI
+    #[kind(raw)]
+    #[name("ACC_CALIB_TIME")]
+    pub acc_calib_time: u32,
+
+    /// Time of the last gyroscope calibration, or 0 if it was never
+    /// calibrated
+    /// Units: seconds since the Unix epoch, as set by the GUI
+    #[kind(raw)]
+    #[name("GYRO_CALIB_TIME")]
+    pub gyro_calib_time: u32,
+
+    /// Temperature of the IMU during the last accelerometer calibration
+    /// Units: 1 degree Celsius
+    #[kind(raw)]
+    #[name("ACC_CALIB_TEMP_CELSIUS")]
+    pub acc_calib_temp: i8,
+
+    /// Temperature of the IMU during the last gyroscope calibration
+    /// Units: 1 degree Celsius
+    #[kind(raw)]
+    #[name("GYRO_CALIB_TEMP_CELSIUS")]
+    pub gyro_calib_temp: i8,
+
+    /// Model of the IMU sensor, as listed in the spec
+    #[kind(raw)]
+    #[name("IMU_MODEL")]
+    pub imu_model: u8,
+
+    #[kind(flags)]
+    #[format(u8)]
+    #[name("IMU_SENSOR_STATUS")]
+    #[cfg_attr(feature = "serde", serde(with = "crate::flag_names"))]
+    pub sensor_status: BitFlags<ImuSensorStatus>,
+
+    /// Quality of the accelerometer calibration of each axis, from 0 (worst)
+    /// to 255 (best)
+    #[kind(payload)]
+    #[name("CALIB_QUALITY")]
+    pub calib_quality: RollPitchYaw<u8>,
+}
+
+/// What [`Gimbal::calibrate`](crate::Gimbal::calibrate) reports while a
+/// calibration runs.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CalibEvent {
+    /// The controller accepted the calibration command.
+    Started,
+    /// The controller reported the progress of the calibration, in percent.
+    Progress(u8),
+    /// The controller confirmed that the calibration is done.
+    Finished,
+}
@@ -1,10 +1,32 @@
-use crate::{Payload, PayloadParseError};
+use crate::{
+    FieldError, FieldPath, FieldValue, Payload, PayloadFields, PayloadParseError, PayloadSize,
+};
 use bytes::{Buf, BufMut, Bytes, BytesMut};
+use num_traits::FromPrimitive;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ConfirmData {
     pub cmd_id: u8,
-    pub data: Option<u16>,
+    pub data: Option<ConfirmValue>,
+}
+
+/// DATA of a `CMD_CONFIRM`, which is 1 or 2 bytes depending on the command
+/// that is confirmed.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ConfirmValue {
+    U8(u8),
+    U16(u16),
+}
+
+impl ConfirmValue {
+    pub fn value(&self) -> u16 {
+        match *self {
+            ConfirmValue::U8(value) => value as u16,
+            ConfirmValue::U16(value) => value,
+        }
+    }
 }
 
 impl Payload for ConfirmData {
@@ -16,12 +38,10 @@ impl Payload for ConfirmData {
             cmd_id: read_enum!(b, "CMD_ID", u8)?,
             // For some reason I was observing behavior inconsistent with the docs where 
             // I was getting 0 bytes here when it says there should be 1 or 2 bytes.
-            data: if b.remaining() == 0 {
-                None
-            } else if b.remaining() == 1 {
-                Some(read_enum!(b, "DATA", u8)?)
-            } else {
-                Some(read_enum!(b, "DATA", u16)?)
+            data: match b.remaining() {
+                0 => None,
+                1 => Some(ConfirmValue::U8(b.get_u8())),
+                _ => Some(ConfirmValue::U16(b.get_u16_le())),
             },
         })
     }
@@ -30,25 +50,25 @@ impl Payload for ConfirmData {
     where
         Self: Sized,
     {
-        let b = match self.data {
-            None => {
-                let mut b = BytesMut::with_capacity(1);
-                b.put_u8(self.cmd_id);
-                b
-            }
-            Some(data_raw) => {
-                let mut b = BytesMut::with_capacity(3);
-                b.put_u8(self.cmd_id);
-                b.put_u16(data_raw);
-                b
-            }
-        };
+        let mut b = BytesMut::with_capacity(3);
+        b.put_u8(self.cmd_id);
+        match self.data {
+            None => {}
+            Some(ConfirmValue::U8(value)) => b.put_u8(value),
+            Some(ConfirmValue::U16(value)) => b.put_u16_le(value),
+        }
 
         b.freeze()
     }
 }
 
+impl PayloadSize for ConfirmData {
+    const SIZE: usize = 3;
+    const MIN_SIZE: usize = 1;
+}
+
 #[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ErrorData {
     #[kind(raw)]
     pub error_code: u8,
@@ -56,3 +76,48 @@ pub struct ErrorData {
     #[kind(raw)]
     pub error_data: [u8; 4],
 }
+
+impl ErrorData {
+    /// Returns the typed error code, or `None` if the controller sent a
+    /// code that is not listed in the spec.
+    pub fn code(&self) -> Option<ErrorCode> {
+        FromPrimitive::from_u8(self.error_code)
+    }
+}
+
+#[derive(FromPrimitive, ToPrimitive, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum ErrorCode {
+    /// Wrong payload size
+    CmdSize = 1,
+    /// Wrong parameters were given to the command
+    WrongParams = 2,
+    /// Crypto-chip could not be read
+    GetDeviceId = 3,
+    /// Board is not activated
+    Crypto = 4,
+    /// Battery voltage calibration failed
+    CalibrateBat = 5,
+    /// Command is not supported by this board or firmware
+    UnknownCommand = 6,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use bytes::Bytes;
+
+    #[test]
+    fn confirm_data() {
+        let b = Bytes::from_static(&[22, 0x34, 0x12]);
+        let confirm = ConfirmData::from_bytes(b.clone()).unwrap();
+        assert_eq!(confirm.data, Some(ConfirmValue::U16(0x1234)));
+        assert_eq!(confirm.to_bytes(), b);
+
+        let b = Bytes::from_static(&[22, 0x34]);
+        let confirm = ConfirmData::from_bytes(b.clone()).unwrap();
+        assert_eq!(confirm.data.map(|data| data.value()), Some(0x34));
+        assert_eq!(confirm.to_bytes(), b);
+    }
+}
@@ -1,9 +1,13 @@
-use crate::{Payload, PayloadParseError, RollPitchYaw};
+use crate::{
+    Angle14, FieldError, FieldPath, FieldValue, HighResRcSignal, Payload, PayloadFields,
+    PayloadParseError, PayloadSize, RcSignal, RollPitchYaw, SpeedUnits,
+};
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use enumflags2::{BitFlags, bitflags};
 use num_traits::{FromPrimitive, ToPrimitive};
 
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ControlFormat {
     /// Mode is common for all axes
     Legacy(AxisControlState),
@@ -12,12 +16,15 @@ pub enum ControlFormat {
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AxisControlState {
     pub mode: AxisControlMode,
+    #[cfg_attr(feature = "serde", serde(with = "crate::flag_names"))]
     pub flags: BitFlags<AxisControlFlags>,
 }
 
 #[derive(FromPrimitive, ToPrimitive, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum AxisControlMode {
     /// If this mode is set for all axes, finish serial control and
@@ -71,6 +78,7 @@ pub enum AxisControlMode {
 
 #[bitflags]
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum AxisControlFlags {
     /// If mode is one of the <MODE_ANGLE,
@@ -137,6 +145,7 @@ impl ToPrimitive for AxisControlState {
 }
 
 #[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AxisControlParams {
     /// Speed of rotation. Overrides the speed settings in the GUI and
     /// from the adjustable variables.
@@ -150,27 +159,51 @@ pub struct AxisControlParams {
     ///   decreased near target to prevent jerks when the
     ///   ANGLE parameter given with the high rate, changes
     ///   slowly;
+    ///
     /// Units: 0,1220740379 deg./sec.
     /// (0.001 deg./sec., if the CONTROL_FLAG_HIGH_RES_SPEED
     /// is set)
-    #[kind(raw)]
+    #[kind(payload)]
     #[name("SPEED")]
-    pub speed: i16,
+    pub speed: SpeedUnits,
 
     /// Depends on the MODE parameter:
     /// - MODE_ANGLE, MODE_SPEED_ANGLE: encodes the target angle
     /// - MODE_SPEED: ignored
     /// - MODE_RC: encodes RC signal in range -500..500
     /// - MODE_RC_HIGH_RES: encodes RC signal in range -16384..16384
-    /// Units: 0,02197265625 degree.
-    #[kind(raw)]
+    ///
+    /// Units: 0,02197265625 degree. In the RC modes this holds the
+    /// raw RC signal; use [`AxisControlParams::rc`] or
+    /// [`AxisControlParams::rc_high_res`] to build it.
+    #[kind(payload)]
     #[name("ANGLE")]
-    pub angle: i16,
+    pub angle: Angle14,
+}
+
+impl AxisControlParams {
+    /// Parameters for MODE_RC, where ANGLE carries an RC signal.
+    pub fn rc(speed: SpeedUnits, signal: RcSignal) -> Self {
+        AxisControlParams {
+            speed,
+            angle: Angle14::from_raw(signal.0),
+        }
+    }
+
+    /// Parameters for MODE_RC_HIGH_RES, where ANGLE carries a high
+    /// resolution RC signal.
+    pub fn rc_high_res(speed: SpeedUnits, signal: HighResRcSignal) -> Self {
+        AxisControlParams {
+            speed,
+            angle: Angle14::from_raw(signal.0),
+        }
+    }
 }
 
-payload_rpy!(AxisControlParams, 4);
+payload_rpy!(AxisControlParams);
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ControlData {
     pub mode: ControlFormat,
     pub axes: RollPitchYaw<AxisControlParams>,
@@ -219,3 +252,131 @@ impl Payload for ControlData {
         b.freeze()
     }
 }
+
+impl PayloadSize for ControlData {
+    const SIZE: usize = 3 + <RollPitchYaw<AxisControlParams> as PayloadSize>::SIZE;
+    const MIN_SIZE: usize = 1 + <RollPitchYaw<AxisControlParams> as PayloadSize>::SIZE;
+}
+
+#[bitflags]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u16)]
+pub enum ControlConfigFlags {
+    /// Do not send `CMD_CONFIRM` in reply to `CMD_CONTROL`
+    NoConfirm = 1 << 0,
+    /// Keep accepting `CMD_CONTROL` while an axis is controlled by RC
+    SerialControlInRcMode = 1 << 1,
+}
+
+/// Overrides of the profile settings for one axis while it is controlled with
+/// `CMD_CONTROL`.
+#[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[size(7)]
+pub struct AxisControlConfig {
+    /// Filter applied to the target angle, or 0 to use the RC_LPF setting
+    #[kind(raw)]
+    #[name("ANGLE_LPF")]
+    #[range(0..=16)]
+    pub angle_lpf: u8,
+
+    /// Filter applied to the target speed, or 0 to use the RC_LPF setting
+    #[kind(raw)]
+    #[name("SPEED_LPF")]
+    #[range(0..=16)]
+    pub speed_lpf: u8,
+
+    /// Filter applied to the RC signal in MODE_RC, or 0 to use the RC_LPF
+    /// setting
+    #[kind(raw)]
+    #[name("RC_LPF")]
+    #[range(0..=16)]
+    pub rc_lpf: u8,
+
+    /// Acceleration limit, or 0 to use the profile setting
+    /// Units: 1 degree/sec²
+    #[kind(raw)]
+    #[name("ACC_LIMIT")]
+    pub acc_limit: u16,
+
+    /// How fast the acceleration may change, or 0 for no limit
+    #[kind(raw)]
+    #[name("JERK_SLOPE")]
+    pub jerk_slope: u8,
+
+    #[kind(raw)]
+    #[name("RESERVED")]
+    pub reserved: u8,
+}
+
+payload_rpy!(AxisControlConfig);
+
+/// Configures how `CMD_CONTROL` is handled, without changing the profile.
+/// (frw. ver. 2.61+)
+#[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[size(41)]
+pub struct ControlConfig {
+    /// How long to wait for the next `CMD_CONTROL` before giving control back
+    /// to the RC inputs, or 0 to wait forever
+    /// Units: ms
+    #[kind(raw)]
+    #[name("TIMEOUT_MS")]
+    pub timeout_ms: u16,
+
+    /// Priority of `CMD_CONTROL` from each serial port, from 1 (lowest) to
+    /// 255, or 0 to keep the current priority
+    #[kind(raw)]
+    #[name("CH_PRIORITY")]
+    pub ch_priority: [u8; 4],
+
+    /// Priority of `CMD_CONTROL` from the port this command is sent on, or 0
+    /// to keep the current priority
+    #[kind(raw)]
+    #[name("THIS_CH_PRIORITY")]
+    pub this_ch_priority: u8,
+
+    #[kind(payload)]
+    #[name("AXIS")]
+    pub axes: RollPitchYaw<AxisControlConfig>,
+
+    /// Expo curve applied to the RC signal in MODE_RC, or 0 for linear
+    #[kind(raw)]
+    #[name("RC_EXPO_RATE")]
+    #[range(0..=100)]
+    pub rc_expo_rate: u8,
+
+    #[kind(flags)]
+    #[format(u16)]
+    #[name("FLAGS")]
+    #[cfg_attr(feature = "serde", serde(with = "crate::flag_names"))]
+    pub flags: BitFlags<ControlConfigFlags>,
+
+    #[kind(raw)]
+    #[name("RESERVED")]
+    pub reserved: [u8; 10],
+}
+
+impl Default for ControlConfig {
+    /// Keeps every setting of the profile, and confirms every `CMD_CONTROL`.
+    fn default() -> Self {
+        let axis = AxisControlConfig {
+            angle_lpf: 0,
+            speed_lpf: 0,
+            rc_lpf: 0,
+            acc_limit: 0,
+            jerk_slope: 0,
+            reserved: 0,
+        };
+        ControlConfig {
+            timeout_ms: 0,
+            ch_priority: [0; 4],
+            this_ch_priority: 0,
+            axes: RollPitchYaw::from((axis, axis, axis)),
+            rc_expo_rate: 0,
+            flags: BitFlags::empty(),
+            reserved: [0; 10],
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use crate::*;
+
+/// Commands that the controller can send periodically and that this crate
+/// can decode.
+#[derive(FromPrimitive, ToPrimitive, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum StreamCommand {
+    RealtimeData3 = 23,
+    RealtimeData4 = 25,
+    RealtimeDataCustom = 88,
+    GetAngles = 73,
+    GetAnglesExt = 61,
+}
+
+/// Register or update a command to be sent to the GUI periodically.
+/// (frw. ver. 2.60+)
+#[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[size(21)]
+pub struct DataStreamInterval {
+    /// Command to be sent
+    #[kind(enumeration)]
+    #[format(u8)]
+    #[name("CMD_ID")]
+    pub cmd_id: StreamCommand,
+
+    /// Interval between commands in milliseconds, or in units of the IMU
+    /// sampling rate if SYNC_TO_DATA is set. 0 stops sending the command.
+    #[kind(raw)]
+    #[name("INTERVAL_MS")]
+    pub interval_ms: u16,
+
+    /// Configuration specific to the command being sent
+    #[kind(raw)]
+    #[name("CONFIG")]
+    pub config: [u8; 8],
+
+    /// If set, the command is sent in sync with the IMU sampling instead of
+    /// at a fixed rate.
+    #[kind(raw)]
+    #[format(u8)]
+    #[name("SYNC_TO_DATA")]
+    pub sync_to_data: bool,
+
+    #[kind(raw)]
+    #[name("RESERVED")]
+    pub reserved: [u8; 9],
+}
+
+impl DataStreamInterval {
+    /// Sends `cmd_id` every `interval_ms` milliseconds.
+    pub fn new(cmd_id: StreamCommand, interval_ms: u16) -> Self {
+        DataStreamInterval {
+            cmd_id,
+            interval_ms,
+            config: [0; 8],
+            sync_to_data: false,
+            reserved: [0; 9],
+        }
+    }
+
+    /// Stops sending `cmd_id`.
+    pub fn disable(cmd_id: StreamCommand) -> Self {
+        Self::new(cmd_id, 0)
+    }
+}
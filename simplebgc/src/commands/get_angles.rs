@@ -1,25 +1,58 @@
-use crate::{Payload, PayloadParseError, RollPitchYaw};
+use crate::{
+    Angle14, FieldError, FieldPath, FieldValue, Payload, PayloadFields, PayloadParseError,
+    PayloadSize, RollPitchYaw, SpeedUnits,
+};
 use bytes::{BufMut, Bytes, BytesMut};
 
 #[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AngleInfo {
     /// Imu angles in 14-bit resolution per full turn
     /// Units: 0,02197265625 degree
-    #[kind(raw)]
+    #[kind(payload)]
     #[name("IMU_ANGLE")]
-    pub imu_angle: i16,
+    pub imu_angle: Angle14,
 
     /// Target angles in 14-bit resolution per full turn
     /// Units: 0,02197265625 degree
-    #[kind(raw)]
+    #[kind(payload)]
     #[name("TARGET_ANGLE")]
-    pub target_angle: i16,
+    pub target_angle: Angle14,
 
     /// Target speed that gimbal should keep, over Euler axes
     /// Units: 0,1220740379 degree/sec
-    #[kind(raw)]
+    #[kind(payload)]
     #[name("TARGET_SPEED")]
-    pub target_speed: i16,
+    pub target_speed: SpeedUnits,
+}
+
+payload_rpy!(AngleInfo);
+
+#[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AngleInfoExt {
+    /// Imu angles in 14-bit resolution per full turn
+    /// Units: 0,02197265625 degree
+    #[kind(payload)]
+    #[name("IMU_ANGLE")]
+    pub imu_angle: Angle14,
+
+    /// Target angles in 14-bit resolution per full turn
+    /// Units: 0,02197265625 degree
+    #[kind(payload)]
+    #[name("TARGET_ANGLE")]
+    pub target_angle: Angle14,
+
+    /// Relative angle of the joint between two arms of the gimbal, where 0
+    /// is the middle of its full range. Not limited to one full turn.
+    /// Units: 0,02197265625 degree
+    #[kind(raw)]
+    #[name("STATOR_ROTOR_ANGLE")]
+    pub stator_rotor_angle: i32,
+
+    #[kind(raw)]
+    #[name("RESERVED")]
+    pub reserved: [u8; 10],
 }
 
-payload_rpy!(AngleInfo, 6);
+payload_rpy!(AngleInfoExt);
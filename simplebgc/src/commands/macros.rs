@@ -7,7 +7,9 @@ macro_rules! read_enum {
         use num_traits::{FromPrimitive};
 
         paste! {
-            FromPrimitive::[< from_ $kind >]($buf.[< get_ $repr >]()).ok_or(PayloadParseError::InvalidEnum { name: $name.into() })
+            $crate::payload::ensure_remaining(&$buf, $name, std::mem::size_of::<$repr>()).and_then(|_| {
+                FromPrimitive::[< from_ $kind >]($buf.[< get_ $repr >]()).ok_or(PayloadParseError::InvalidEnum { name: $name.into() })
+            })
         }
     }};
 }
@@ -16,7 +18,9 @@ macro_rules! read_enum {
 macro_rules! read_flags {
     ($buf: ident, $name: literal, $repr: ident) => {{
         paste! {
-            BitFlags::from_bits($buf.[< get_ $repr >]()).or(Err(PayloadParseError::InvalidFlags { name: $name.into() }))
+            $crate::payload::ensure_remaining(&$buf, $name, std::mem::size_of::<$repr>()).and_then(|_| {
+                BitFlags::from_bits($buf.[< get_ $repr >]()).or(Err(PayloadParseError::InvalidFlags { name: $name.into() }))
+            })
         }
     }}
 }
@@ -25,7 +29,8 @@ macro_rules! read_flags {
 macro_rules! read_flags_truncate {
     ($buf: ident, $name: literal, $repr: ident) => {{
         paste! {
-            BitFlags::from_bits_truncate($buf.[< get_ $repr >]())
+            $crate::payload::ensure_remaining(&$buf, $name, std::mem::size_of::<$repr>())
+                .map(|_| BitFlags::from_bits_truncate($buf.[< get_ $repr >]()))
         }
     }};
 }
@@ -0,0 +1,236 @@
+use crate::*;
+use bytes::{Buf, Bytes};
+use num_traits::*;
+
+macro_rules! menu_commands {
+    ($($(#[$attr:meta])* $variant:ident = $id:literal,)*) => {
+        /// Actions of the menu button, which can also be assigned to RC
+        /// commands or executed with `CMD_EXECUTE_MENU`.
+        #[derive(Copy, Clone, Debug, PartialEq)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+        pub enum MenuCommand {
+            $($(#[$attr])* $variant,)*
+            /// An action this crate does not know about, such as one added by
+            /// newer firmware
+            Other(u8),
+        }
+
+        impl MenuCommand {
+            /// Returns the command with this MENU_CMD id, which is
+            /// [`MenuCommand::Other`] for unknown ids.
+            pub fn from_id(id: u8) -> Self {
+                match id {
+                    $($id => MenuCommand::$variant,)*
+                    _ => MenuCommand::Other(id),
+                }
+            }
+
+            /// Returns the MENU_CMD id of this command.
+            pub fn id(&self) -> u8 {
+                match *self {
+                    $(MenuCommand::$variant => $id,)*
+                    MenuCommand::Other(id) => id,
+                }
+            }
+        }
+    };
+}
+
+menu_commands! {
+    NoAction = 0,
+    UseProfile1 = 1,
+    UseProfile2 = 2,
+    UseProfile3 = 3,
+    SwapPitchRoll = 4,
+    SwapYawRoll = 5,
+    CalibAcc = 6,
+    Reset = 7,
+    SetAngle = 8,
+    CalibGyro = 9,
+    MotorToggle = 10,
+    MotorOn = 11,
+    MotorOff = 12,
+    FrameUpsideDown = 13,
+    UseProfile4 = 14,
+    UseProfile5 = 15,
+    AutoPid = 16,
+    LookDown = 17,
+    HomePosition = 18,
+    RcBind = 19,
+    CalibGyroTemp = 20,
+    CalibAccTemp = 21,
+    ButtonPress = 22,
+    RunScript1 = 23,
+    RunScript2 = 24,
+    RunScript3 = 25,
+    RunScript4 = 26,
+    RunScript5 = 27,
+    CalibMag = 28,
+    LevelRollPitch = 29,
+    CenterYaw = 30,
+    UntwistCables = 31,
+    /// Same as `SetAngle`, without saving the angle to the EEPROM
+    SetAngleNoSave = 32,
+    HomePositionShortest = 33,
+    CenterYawShortest = 34,
+    RotateYaw180 = 35,
+    RotateYaw180FrameRel = 36,
+    SwitchYaw180FrameRel = 37,
+    SwitchPosRoll90 = 38,
+    StartTimelapse = 39,
+    CalibMomentum = 40,
+    LevelRoll = 41,
+    RepeatTimelapse = 42,
+    LoadProfileSet1 = 43,
+    LoadProfileSet2 = 44,
+    LoadProfileSet3 = 45,
+    LoadProfileSet4 = 46,
+    LoadProfileSet5 = 47,
+    LoadProfileSetBackup = 48,
+    InvertRcRoll = 49,
+    InvertRcPitch = 50,
+    InvertRcYaw = 51,
+    SnapToFixedPosition = 52,
+    CameraRecPhotoEvent = 53,
+    CameraPhotoEvent = 54,
+    MotorsSafeStop = 55,
+    CalibAccAuto = 56,
+    ResetImu = 57,
+    ForcedFollowToggle = 58,
+    AutoPidGainOnly = 59,
+    LevelPitch = 60,
+    MotorsSafeToggle = 61,
+    TimelapseStep1 = 62,
+    TimelapseStep2 = 63,
+    /// Makes the current position of the camera the new home position
+    SetCurPosAsHome = 64,
+    /// Switches between the main IMU and the frame IMU
+    SwitchImu = 65,
+}
+
+/// Every id in `0..=255` is a valid menu command.
+impl FromPrimitive for MenuCommand {
+    fn from_i64(n: i64) -> Option<Self> {
+        n.to_u8().map(MenuCommand::from_id)
+    }
+
+    fn from_u64(n: u64) -> Option<Self> {
+        n.to_u8().map(MenuCommand::from_id)
+    }
+}
+
+impl ToPrimitive for MenuCommand {
+    fn to_i64(&self) -> Option<i64> {
+        Some(self.id() as i64)
+    }
+
+    fn to_u64(&self) -> Option<u64> {
+        Some(self.id() as u64)
+    }
+}
+
+/// `CMD_EXECUTE_MENU` only carries the command, as CMD_ID.
+impl Payload for MenuCommand {
+    fn from_bytes(mut b: Bytes) -> Result<Self, PayloadParseError>
+    where
+        Self: Sized,
+    {
+        crate::payload::ensure_remaining(&b, "CMD_ID", 1)?;
+        Ok(MenuCommand::from_id(b.get_u8()))
+    }
+
+    fn to_bytes(&self) -> Bytes
+    where
+        Self: Sized,
+    {
+        Bytes::copy_from_slice(&[self.id()])
+    }
+}
+
+impl PayloadSize for MenuCommand {
+    const SIZE: usize = 1;
+}
+
+/// Menu commands assigned to the RC_CMD input and to the menu button.
+#[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RcCmds {
+    /// Executed when the RC_CMD input goes to its low position
+    #[kind(enumeration)]
+    #[format(u8)]
+    #[name("RC_CMD_LOW")]
+    pub low: MenuCommand,
+
+    /// Executed when the RC_CMD input goes to its middle position
+    #[kind(enumeration)]
+    #[format(u8)]
+    #[name("RC_CMD_MID")]
+    pub mid: MenuCommand,
+
+    /// Executed when the RC_CMD input goes to its high position
+    #[kind(enumeration)]
+    #[format(u8)]
+    #[name("RC_CMD_HIGH")]
+    pub high: MenuCommand,
+
+    /// Executed when the menu button is pressed once
+    #[kind(enumeration)]
+    #[format(u8)]
+    #[name("MENU_CMD_1")]
+    pub menu_1: MenuCommand,
+
+    /// Executed when the menu button is pressed twice
+    #[kind(enumeration)]
+    #[format(u8)]
+    #[name("MENU_CMD_2")]
+    pub menu_2: MenuCommand,
+
+    /// Executed when the menu button is pressed 3 times
+    #[kind(enumeration)]
+    #[format(u8)]
+    #[name("MENU_CMD_3")]
+    pub menu_3: MenuCommand,
+
+    /// Executed when the menu button is pressed 4 times
+    #[kind(enumeration)]
+    #[format(u8)]
+    #[name("MENU_CMD_4")]
+    pub menu_4: MenuCommand,
+
+    /// Executed when the menu button is pressed 5 times
+    #[kind(enumeration)]
+    #[format(u8)]
+    #[name("MENU_CMD_5")]
+    pub menu_5: MenuCommand,
+
+    /// Executed when the menu button is held down
+    #[kind(enumeration)]
+    #[format(u8)]
+    #[name("MENU_CMD_LONG")]
+    pub menu_long: MenuCommand,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use bytes::Bytes;
+
+    #[test]
+    fn rc_cmds() {
+        let mut b = crate::fields::tests::params3().to_bytes().to_vec();
+        // RC_CMD_LOW .. MENU_CMD_LONG
+        b[79..88].copy_from_slice(&[12, 0, 11, 1, 2, 3, 14, 15, 17]);
+        let params = Params3Data::from_bytes(Bytes::from(b.clone())).unwrap();
+        assert_eq!(params.rc_cmd.low, MenuCommand::MotorOff);
+        assert_eq!(params.rc_cmd.mid, MenuCommand::NoAction);
+        assert_eq!(params.rc_cmd.menu_4, MenuCommand::UseProfile4);
+        assert_eq!(params.rc_cmd.menu_long, MenuCommand::LookDown);
+        assert_eq!(&params.to_bytes()[..], &b[..]);
+
+        // ids unknown to this crate are kept as they are
+        b[87] = 0xFF;
+        let params = Params3Data::from_bytes(Bytes::from(b.clone())).unwrap();
+        assert_eq!(params.rc_cmd.menu_long, MenuCommand::Other(0xFF));
+        assert_eq!(&params.to_bytes()[..], &b[..]);
+    }
+}
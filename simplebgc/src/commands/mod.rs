@@ -2,48 +2,78 @@
 pub(crate) mod macros;
 pub(crate) mod constants;
 
+mod adj_vars;
+mod auto_pid;
 mod board_info;
+mod calib;
 mod cmd_response;
 mod control;
+mod data_stream;
 mod get_angles;
+mod menu;
 mod motors_off;
 mod read_params;
+mod read_params_ext;
 mod realtime;
+mod realtime_custom;
+mod virt_ch;
 
+pub use self::adj_vars::*;
+pub use self::auto_pid::*;
 pub use self::board_info::*;
+pub use self::calib::*;
 pub use self::cmd_response::*;
 pub use self::control::*;
+pub use self::data_stream::*;
 pub use self::get_angles::*;
+pub use self::menu::*;
 pub use self::motors_off::*;
 pub use self::read_params::*;
+pub use self::read_params_ext::*;
 pub use self::realtime::*;
+pub use self::realtime_custom::*;
+pub use self::virt_ch::*;
 
-use crate::{Payload, PayloadParseError, RollPitchYaw};
+use crate::{Payload, PayloadParseError, PayloadSize, RollPitchYaw};
 use bytes::{BufMut, Bytes, BytesMut};
 
-payload_rpy!(u8, 1);
-payload_rpy!(i8, 1);
-payload_rpy!(u16, 2);
-payload_rpy!(i16, 2);
+payload_rpy!(u8);
+payload_rpy!(i8);
+payload_rpy!(u16);
+payload_rpy!(i16);
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum IncomingCommand {
     CommandConfirm(ConfirmData),
     CommandError(ErrorData),
+    AdjVarsState(AdjVarsState),
+    AutoPid(AutoPidProgress),
     BoardInfo(BoardInfo),
     BoardInfo3(BoardInfo3),
+    CalibInfo(CalibInfo),
     GetAngles(RollPitchYaw<AngleInfo>),
+    GetAnglesExt(RollPitchYaw<AngleInfoExt>),
+    ReadAdjVarsCfg(AdjVarsCfg),
     ReadParams(Params3Data),
     ReadParams3(Params3Data),
+    ReadParamsExt(ParamsExtData),
+    ReadParamsExt2(ParamsExt2Data),
+    ReadParamsExt3(ParamsExt3Data),
     RealtimeData3(RealtimeData3),
+    RealtimeData4(RealtimeData4),
+    RealtimeDataCustom(RealtimeDataCustomRaw),
+    SetAdjVarsVal(AdjVarsValues),
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum OutgoingCommand {
     BoardInfo,
     BoardInfo3,
     Reset,
     Control(ControlData),
+    ControlConfig(ControlConfig),
     MotorsOn,
     MotorsOff(MotorsOffQuery),
     ReadParams(ParamsQuery),
@@ -53,8 +83,37 @@ pub enum OutgoingCommand {
     ReadParamsExt3(ParamsQuery),
     WriteParams(Params3Data),
     WriteParams3(Params3Data),
+    WriteParamsExt(ParamsExtData),
+    WriteParamsExt2(ParamsExt2Data),
+    WriteParamsExt3(ParamsExt3Data),
+    SaveParams3(ParamsQuery),
+    UseDefaults(ParamsQuery),
     RealtimeData3,
+    RealtimeData4,
+    RealtimeDataCustom(RealtimeDataCustomQuery),
     GetAngles,
     GetAnglesExt,
+    DataStreamInterval(DataStreamInterval),
+    ExecuteMenu(MenuCommand),
+    CalibAcc(CalibQuery),
+    CalibGyro(CalibQuery),
+    CalibMag(CalibQuery),
+    CalibPoles,
+    CalibOffset,
+    CalibExtGain,
+    CalibMotorMagLink,
+    CalibBat(CalibBatQuery),
+    CalibAccExtRef(CalibAccExtRefQuery),
+    CalibOrientCorr(CalibOrientCorrQuery),
+    CalibInfo(CalibInfoQuery),
+    AutoPid(AutoPidQuery),
+    AutoPid2(AutoPid2Query),
+    SetAdjVarsVal(AdjVarsValues),
+    GetAdjVarsVal(AdjVarsQuery),
+    AdjVarsState(AdjVarsStateQuery),
+    ReadAdjVarsCfg,
+    WriteAdjVarsCfg(AdjVarsCfg),
+    ApiVirtChControl(VirtChControl),
+    ApiVirtChHighRes(VirtChHighRes),
     Other { id: u8 },
 }
@@ -2,6 +2,7 @@ use crate::*;
 use num_traits::*;
 
 #[derive(FromPrimitive, ToPrimitive, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum MotorsOffMode {
     /// turn motors off leaving driver in a high impedance
@@ -14,6 +15,7 @@ pub enum MotorsOffMode {
 }
 
 #[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MotorsOffQuery(
     #[kind(enumeration)]
     #[name("")]
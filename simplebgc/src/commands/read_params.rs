@@ -4,6 +4,7 @@ use enumflags2::{BitFlags, bitflags};
 use num_traits::*;
 
 #[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AxisPidParams {
     #[kind(raw)]
     pub p: u8,
@@ -20,34 +21,46 @@ pub struct AxisPidParams {
     pub poles: u8,
 }
 
-payload_rpy!(AxisPidParams, 6);
+payload_rpy!(AxisPidParams);
 
 #[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AxisRcParams {
     /// Units: degrees
     #[kind(raw)]
+    #[name("RC_MIN_ANGLE")]
+    #[range(-720..=720)]
     pub rc_min_angle: i16,
     /// Units: degrees
     #[kind(raw)]
+    #[name("RC_MAX_ANGLE")]
+    #[range(-720..=720)]
     pub rc_max_angle: i16,
     #[kind(enumeration)]
     #[format(u8)]
+    #[name("RC_MODE")]
     pub rc_mode: AxisRcMode,
     #[kind(raw)]
+    #[name("RC_LPF")]
+    #[range(0..=16)]
     pub rc_lpf: u8,
     #[kind(raw)]
+    #[name("RC_SPEED")]
     pub rc_speed: u8,
 
     /// ROLL, PITCH: this value specify follow rate for
     /// flight controller. YAW: if value != 0, “follow motor”
     /// mode is enabled.
     #[kind(raw)]
+    #[name("RC_FOLLOW")]
+    #[range(-127..=127)]
     pub rc_follow: i8,
 }
 
-payload_rpy!(AxisRcParams, 8);
+payload_rpy!(AxisRcParams);
 
 #[derive(FromPrimitive, ToPrimitive, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum AxisRcMode {
     AngleRegular = 0b0000,
@@ -59,6 +72,7 @@ pub enum AxisRcMode {
 }
 
 #[derive(FromPrimitive, ToPrimitive, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum PwmFrequency {
     Low = 0,
@@ -67,6 +81,7 @@ pub enum PwmFrequency {
 }
 
 #[derive(FromPrimitive, ToPrimitive, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum SerialSpeed {
     /// 115200
@@ -84,6 +99,7 @@ pub enum SerialSpeed {
 }
 
 #[derive(FromPrimitive, ToPrimitive, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum RcVirtMode {
     Normal = 0,
@@ -94,6 +110,7 @@ pub enum RcVirtMode {
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RcMap {
     None,
     PWM { source: RcMapPwmSource },
@@ -104,6 +121,7 @@ pub enum RcMap {
 }
 
 #[derive(FromPrimitive, ToPrimitive, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum RcMapPwmSource {
     Roll,
@@ -114,6 +132,7 @@ pub enum RcMapPwmSource {
 }
 
 #[derive(FromPrimitive, ToPrimitive, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum RcMapAnalogChannel {
     ADC1 = 1,
@@ -121,6 +140,24 @@ pub enum RcMapAnalogChannel {
     ADC3 = 4,
 }
 
+impl RcMap {
+    /// Returns false if the channel does not exist on the controller.
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            RcMap::Serial { channel } => channel <= 17,
+            RcMap::Virtual { channel } => channel <= 31,
+            RcMap::Step { channel } => channel <= 5,
+            _ => true,
+        }
+    }
+}
+
+/// Returns false unless `profile_id` is one of the 5 profiles, or 255 for the
+/// active one.
+fn is_valid_profile_id(profile_id: &u8) -> bool {
+    *profile_id <= 4 || *profile_id == 255
+}
+
 impl FromPrimitive for RcMap {
     fn from_i64(n: i64) -> Option<Self> {
         FromPrimitive::from_u8(n as u8)
@@ -132,7 +169,7 @@ impl FromPrimitive for RcMap {
         }
 
         let channel = b & 0b11111;
-        let kind = (b & 0b00000111) >> 5;
+        let kind = b >> 5;
 
         Some(match kind {
             0 => RcMap::PWM {
@@ -175,6 +212,7 @@ impl ToPrimitive for RcMap {
 }
 
 #[derive(FromPrimitive, ToPrimitive, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum RcMixChannel {
     None = 0,
@@ -184,6 +222,7 @@ pub enum RcMixChannel {
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RcMix {
     pub rc_mix_rate: u8,
     pub rc_mix_channel: RcMixChannel,
@@ -194,14 +233,13 @@ impl Payload for RcMix {
     where
             Self: Sized {
         // We expect one byte
-        if b.len() < 1 {
-            return Err(PayloadParseError::InvalidFlags { name: "RcMixRate".into() });
-        }
+        crate::payload::ensure_remaining(&b, "RC_MIX", 1)?;
         let byte = b[0];
         // Bits [0..4] bits are for rc_mix_rate
         let rc_mix_rate = 0b00011111 & byte;
         // Bits [5..7] are for rc_mix_channel, so we right shift 5 to the right
-        let rc_mix_channel = RcMixChannel::from_u8(byte >> 5).unwrap();
+        let rc_mix_channel = RcMixChannel::from_u8(byte >> 5)
+            .ok_or(PayloadParseError::InvalidEnum { name: "RC_MIX".into() })?;
         Ok(RcMix {
             rc_mix_rate,
             rc_mix_channel,
@@ -218,7 +256,45 @@ impl Payload for RcMix {
     }
 }
 
+impl PayloadSize for RcMix {
+    const SIZE: usize = 1;
+}
+
+impl PayloadFields for RcMix {
+    fn visit_fields(&self, path: &FieldPath, visit: &mut dyn FnMut(String, FieldValue)) {
+        visit(path.name("RATE", false), FieldValue::Int(self.rc_mix_rate as i64));
+        visit(
+            path.name("CHANNEL", false),
+            FieldValue::Enum(format!("{:?}", self.rc_mix_channel)),
+        );
+    }
+
+    fn set_field(
+        &mut self,
+        path: &FieldPath,
+        name: &str,
+        value: &FieldValue,
+    ) -> Result<bool, FieldError> {
+        if name == path.name("RATE", false) {
+            self.rc_mix_rate = value.to_int(name)?;
+        } else if name == path.name("CHANNEL", false) {
+            self.rc_mix_channel = value.to_enum(name, 0, 0b111)?;
+        } else {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    fn validate_fields(&self, path: &FieldPath, errors: &mut Vec<FieldError>) {
+        let name = path.name("RATE", false);
+        if let Err(err) = FieldValue::Int(self.rc_mix_rate as i64).check_range(&name, 0, 31) {
+            errors.push(err);
+        }
+    }
+}
+
 #[derive(FromPrimitive, ToPrimitive, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum FollowMode {
     Disabled = 0,
@@ -227,6 +303,7 @@ pub enum FollowMode {
 }
 
 #[derive(FromPrimitive, ToPrimitive, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(i8)]
 pub enum Orientation {
     PosX = 1,
@@ -238,6 +315,7 @@ pub enum Orientation {
 }
 
 #[derive(FromPrimitive, ToPrimitive, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum FrameImuPos {
     Disabled = 0,
@@ -247,6 +325,7 @@ pub enum FrameImuPos {
 }
 
 #[derive(FromPrimitive, ToPrimitive, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum GyroCalibrationMode {
     /// do not skip
@@ -258,6 +337,7 @@ pub enum GyroCalibrationMode {
 }
 
 #[derive(FromPrimitive, ToPrimitive, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum MotorOutput {
     Disabled = 0,
@@ -272,6 +352,7 @@ pub enum MotorOutput {
 
 #[bitflags]
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum BeeperMode {
     Calibrate = 1,
@@ -283,6 +364,7 @@ pub enum BeeperMode {
 
 #[bitflags]
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum AdaptivePid {
     Roll = 1,
@@ -292,6 +374,7 @@ pub enum AdaptivePid {
 
 #[bitflags]
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u16)]
 pub enum GeneralFlags {
     RememberLastUsedProfile = 1 << 0,
@@ -312,6 +395,7 @@ pub enum GeneralFlags {
 
 #[bitflags]
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u16)]
 pub enum ProfileFlags {
     Adc1AutoDetection = 1 << 0,
@@ -331,6 +415,7 @@ pub enum ProfileFlags {
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum SpektrumModeDSM {
     DSM2 = 0,
@@ -338,6 +423,7 @@ pub enum SpektrumModeDSM {
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum SpektrumModeTime {
     /// 11ms
@@ -347,6 +433,7 @@ pub enum SpektrumModeTime {
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum SpektrumModeBits {
     /// 10bits
@@ -356,6 +443,7 @@ pub enum SpektrumModeBits {
 }
 
 #[derive(FromPrimitive, ToPrimitive, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum SpektrumMode {
     Auto = 0,
@@ -370,6 +458,7 @@ pub enum SpektrumMode {
 }
 
 #[derive(FromPrimitive, ToPrimitive, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum AxisOrder {
     PitchRollYaw = 0,
@@ -379,6 +468,7 @@ pub enum AxisOrder {
 }
 
 #[derive(FromPrimitive, ToPrimitive, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum EulerOrder {
     PitchRollYaw = 0,
@@ -390,6 +480,7 @@ pub enum EulerOrder {
 }
 
 #[derive(FromPrimitive, ToPrimitive, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum ImuType {
     Main = 1,
@@ -397,78 +488,88 @@ pub enum ImuType {
 }
 
 #[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RcMixes {
     #[kind(payload)]
     #[name("RC_MIX_FC_ROLL")]
-    #[size(1)]
     pub fc_roll: RcMix,
 
     #[kind(payload)]
     #[name("RC_MIX_FC_PITCH")]
-    #[size(1)]
     pub fc_pitch: RcMix,
 }
 
 #[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RcMaps {
     #[kind(enumeration)]
     #[name("RC_MAP_ROLL")]
     #[format(u8)]
+    #[validate(RcMap::is_valid)]
     pub roll: RcMap,
 
     #[kind(enumeration)]
     #[name("RC_MAP_PITCH")]
     #[format(u8)]
+    #[validate(RcMap::is_valid)]
     pub pitch: RcMap,
 
     #[kind(enumeration)]
     #[name("RC_MAP_YAW")]
     #[format(u8)]
+    #[validate(RcMap::is_valid)]
     pub yaw: RcMap,
 
     #[kind(enumeration)]
     #[name("RC_MAP_CMD")]
     #[format(u8)]
+    #[validate(RcMap::is_valid)]
     pub cmd: RcMap,
 
     #[kind(enumeration)]
     #[name("RC_MAP_FC_ROLL")]
     #[format(u8)]
+    #[validate(RcMap::is_valid)]
     pub fc_roll: RcMap,
 
     #[kind(enumeration)]
     #[name("RC_MAP_FC_PITCH")]
     #[format(u8)]
+    #[validate(RcMap::is_valid)]
     pub fc_pitch: RcMap,
 }
 
 #[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ParamsQuery {
     #[kind(raw)]
     pub profile_id: u8,
 }
 
 #[derive(BgcPayload, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[size(134)]
 pub struct Params3Data {
     /// profile ID to read or write. To access current (active) profile,
     /// specify 255. Possible values: 0..4
     #[kind(raw)]
+    #[validate(is_valid_profile_id)]
     pub profile_id: u8,
 
     #[kind(payload)]
-    #[size(18)]
     pub pid: RollPitchYaw<AxisPidParams>,
 
     /// Units: 5 degrees/sec^2 0 – disabled.
     /// (starting from ver. 2.60 is deprecated; replaced by the ACC_LIMITER3)
     #[kind(raw)]
+    #[range(0..=200)]
     pub acc_limiter_all: u8,
 
     #[kind(raw)]
+    #[range(-127..=127)]
     pub ext_fc_gain: (i8, i8),
 
     #[kind(payload)]
-    #[size(24)]
     pub rc: RollPitchYaw<AxisRcParams>,
 
     #[kind(raw)]
@@ -487,13 +588,14 @@ pub struct Params3Data {
     pub serial_speed: SerialSpeed,
 
     #[kind(payload)]
-    #[size(3)]
+    #[range(-127..=127)]
     pub rc_trim: RollPitchYaw<i8>,
 
     #[kind(raw)]
     pub rc_deadband: u8,
 
     #[kind(raw)]
+    #[range(0..=100)]
     pub rc_expo_rate: u8,
 
     #[kind(enumeration)]
@@ -501,11 +603,9 @@ pub struct Params3Data {
     pub rc_virt_mode: RcVirtMode,
 
     #[kind(payload)]
-    #[size(6)]
     pub rc_map: RcMaps,
 
     #[kind(payload)]
-    #[size(2)]
     pub rc_mix: RcMixes,
 
     #[kind(enumeration)]
@@ -516,10 +616,11 @@ pub struct Params3Data {
     pub follow_deadband: u8,
 
     #[kind(raw)]
+    #[range(0..=100)]
     pub follow_expo_rate: u8,
 
     #[kind(payload)]
-    #[size(3)]
+    #[range(-127..=127)]
     pub follow_offset: RollPitchYaw<i8>,
 
     #[kind(enumeration)]
@@ -556,40 +657,45 @@ pub struct Params3Data {
     #[format(u8)]
     pub skip_gyro_calib: GyroCalibrationMode,
 
-    #[kind(raw)]
-    pub rc_cmd: [u8; 9], // TODO: implement RC_CMD_LOW .. MENU_CMD_LONG, probably as a couple of structs
+    #[kind(payload)]
+    #[size(9)]
+    pub rc_cmd: RcCmds,
 
     #[kind(payload)]
-    #[size(3)]
+    #[range(0..=7)]
     pub motor_output: RollPitchYaw<u8>,
 
     /// Negative means means alarm is disabled.
     #[kind(raw)]
+    #[range(-3000..=3000)]
     pub bat_threshold_alarm: i16,
     /// Negative value means function is disabled.
     #[kind(raw)]
+    #[range(-3000..=3000)]
     pub bat_threshold_motors: i16,
     /// Negative value means compensation is disabled.
     #[kind(raw)]
+    #[range(-3000..=3000)]
     pub bat_comp_ref: i16,
 
     #[kind(flags)]
     #[format(u8)]
+    #[cfg_attr(feature = "serde", serde(with = "crate::flag_names"))]
     pub beeper_mode: BitFlags<BeeperMode>,
 
     #[kind(raw)]
     #[format(u8)]
+    #[range(0..=90)]
     pub follow_roll_mix_start: u8,
     #[kind(raw)]
     #[format(u8)]
+    #[range(0..=90)]
     pub follow_roll_mix_range: u8,
 
     #[kind(payload)]
-    #[size(3)]
     pub booster_power: RollPitchYaw<u8>,
 
     #[kind(payload)]
-    #[size(3)]
     pub follow_speed: RollPitchYaw<u8>,
 
     #[kind(raw)]
@@ -598,39 +704,46 @@ pub struct Params3Data {
     /// Disabled = 0
     /// 1..32 - Virtual channel number as source of data to be output
 
+    /// Last RC target angles
     #[kind(payload)]
-    #[size(6)]
-    pub rc_memory: RollPitchYaw<i16>,
+    pub rc_memory: RollPitchYaw<Angle14>,
 
     #[kind(raw)]
+    #[range(0..=32)]
     pub servo_out: [u8; 4],
     /// PWM frequency, 10 Hz per unit.
     #[kind(raw)]
+    #[range(5..=40)]
     pub servo_rate: u8,
 
     #[kind(flags)]
     #[format(u8)]
+    #[cfg_attr(feature = "serde", serde(with = "crate::flag_names"))]
     pub adaptive_pid_enabled: BitFlags<AdaptivePid>,
 
     #[kind(raw)]
     pub adaptive_pid_threshold: u8,
 
     #[kind(raw)]
+    #[range(1..=255)]
     pub adaptive_pid_rate: u8,
 
     #[kind(raw)]
+    #[range(0..=10)]
     pub adaptive_pid_recovery_factor: u8,
 
     #[kind(payload)]
-    #[size(3)]
+    #[range(0..=16)]
     pub follow_lpf: RollPitchYaw<u8>,
 
     #[kind(flags)]
     #[format(u16)]
+    #[cfg_attr(feature = "serde", serde(with = "crate::flag_names"))]
     pub general_flags: BitFlags<GeneralFlags>,
 
     #[kind(flags)]
     #[format(u16)]
+    #[cfg_attr(feature = "serde", serde(with = "crate::flag_names"))]
     pub profile_flags: BitFlags<ProfileFlags>,
 
     #[kind(enumeration)]
@@ -656,5 +769,30 @@ pub struct Params3Data {
 
     /// profile ID which is currently active in the controller, 0...4
     #[kind(raw)]
+    #[range(0..=4)]
     pub cur_profile_id: u8,
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn rc_map_round_trip() {
+        use num_traits::{FromPrimitive, ToPrimitive};
+
+        for map in [
+            RcMap::PWM {
+                source: RcMapPwmSource::Yaw,
+            },
+            RcMap::Analog {
+                channel: RcMapAnalogChannel::ADC3,
+            },
+            RcMap::Serial { channel: 2 },
+            RcMap::Virtual { channel: 31 },
+            RcMap::Step { channel: 1 },
+        ] {
+            assert_eq!(RcMap::from_u8(map.to_u8().unwrap()), Some(map));
+        }
+    }
+}
@@ -0,0 +1,431 @@
+use crate::*;
+use bytes::{BufMut, Bytes, BytesMut};
+use enumflags2::{bitflags, BitFlags};
+
+#[bitflags]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum FilterFlags {
+    Notch1 = 1 << 0,
+    Notch2 = 1 << 1,
+    Notch3 = 1 << 2,
+    Lpf = 1 << 3,
+}
+
+payload_rpy!(BitFlags<FilterFlags>);
+
+#[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AxisNotchParams {
+    /// Center frequency of each notch filter
+    /// Units: Hz
+    #[kind(raw)]
+    pub notch_freq: [u8; 3],
+
+    /// Width of each notch filter
+    /// Units: Hz
+    #[kind(raw)]
+    pub notch_width: [u8; 3],
+}
+
+payload_rpy!(AxisNotchParams);
+
+/// Extended parameters, part 1.
+/// (frw. ver. 2.40+)
+#[derive(BgcPayload, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[size(104)]
+pub struct ParamsExtData {
+    /// profile ID to read or write. To access current (active) profile,
+    /// specify 255. Possible values: 0..4
+    #[kind(raw)]
+    pub profile_id: u8,
+
+    #[kind(payload)]
+    pub notch: RollPitchYaw<AxisNotchParams>,
+
+    /// Frequency of the low-pass filter
+    /// Units: Hz
+    #[kind(payload)]
+    pub lpf_freq: RollPitchYaw<u16>,
+
+    #[kind(payload)]
+    #[cfg_attr(feature = "serde", serde(with = "crate::flag_names::rpy"))]
+    pub filters_en: RollPitchYaw<BitFlags<FilterFlags>>,
+
+    /// Offset of the encoder from the motor's zero position
+    #[kind(payload)]
+    pub encoder_offset: RollPitchYaw<Angle14>,
+
+    /// Offset of the encoder from the field of the motor
+    #[kind(payload)]
+    pub encoder_fld_offset: RollPitchYaw<Angle14>,
+
+    /// Units: 10 ms
+    #[kind(payload)]
+    pub encoder_manual_set_time: RollPitchYaw<u8>,
+
+    #[kind(payload)]
+    pub motor_heating_factor: RollPitchYaw<u8>,
+
+    #[kind(payload)]
+    pub motor_cooling_factor: RollPitchYaw<u8>,
+
+    #[kind(raw)]
+    pub reserved1: [u8; 2],
+
+    /// Units: 0.1 degree
+    #[kind(raw)]
+    pub follow_inside_deadband: u8,
+
+    #[kind(payload)]
+    pub motor_mag_link: RollPitchYaw<u8>,
+
+    /// Units: 1/256
+    #[kind(payload)]
+    pub motor_gearing: RollPitchYaw<u16>,
+
+    /// Units: 1 degree
+    #[kind(payload)]
+    pub encoder_limit_min: RollPitchYaw<i8>,
+
+    /// Units: 1 degree
+    #[kind(payload)]
+    pub encoder_limit_max: RollPitchYaw<i8>,
+
+    /// Units: dB
+    #[kind(payload)]
+    pub notch1_gain: RollPitchYaw<i8>,
+
+    /// Units: dB
+    #[kind(payload)]
+    pub notch2_gain: RollPitchYaw<i8>,
+
+    /// Units: dB
+    #[kind(payload)]
+    pub notch3_gain: RollPitchYaw<i8>,
+
+    #[kind(raw)]
+    pub beeper_volume: u8,
+
+    /// Units: 1/256
+    #[kind(payload)]
+    pub encoder_gear_ratio: RollPitchYaw<u16>,
+
+    /// bits 0..3: type of the encoder,
+    /// bit 7: encoder is mounted on the motor shaft
+    #[kind(payload)]
+    pub encoder_type: RollPitchYaw<u8>,
+
+    #[kind(payload)]
+    pub encoder_cfg: RollPitchYaw<u8>,
+
+    #[kind(payload)]
+    pub outer_p: RollPitchYaw<u8>,
+
+    #[kind(payload)]
+    pub outer_i: RollPitchYaw<u8>,
+
+    /// Same as AXIS_TOP; 0 means the magnetometer is disabled.
+    #[kind(raw)]
+    pub mag_axis_top: i8,
+
+    /// Same as AXIS_RIGHT; 0 means the magnetometer is disabled.
+    #[kind(raw)]
+    pub mag_axis_right: i8,
+
+    #[kind(raw)]
+    pub mag_trust: u8,
+
+    /// Units: 1 degree
+    #[kind(raw)]
+    pub mag_declination: i8,
+
+    /// Units: Hz
+    #[kind(raw)]
+    pub acc_lpf_freq: u16,
+
+    /// Units: 10 Hz
+    #[kind(payload)]
+    pub d_term_lpf_freq: RollPitchYaw<u8>,
+}
+
+/// Extended parameters, part 2.
+/// (frw. ver. 2.60+)
+#[derive(BgcPayload, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParamsExt2Data {
+    /// profile ID to read or write. To access current (active) profile,
+    /// specify 255. Possible values: 0..4
+    #[kind(raw)]
+    pub profile_id: u8,
+
+    /// Serial port used for MavLink, 0 if disabled
+    #[kind(raw)]
+    pub mav_src: u8,
+
+    #[kind(raw)]
+    pub mav_sys_id: u8,
+
+    #[kind(raw)]
+    pub mav_comp_id: u8,
+
+    #[kind(raw)]
+    pub mav_cfg_flags: u8,
+
+    #[kind(raw)]
+    pub mav_reserved: [u8; 4],
+
+    /// Units: 1/256
+    #[kind(payload)]
+    pub motor_mag_link_fine: RollPitchYaw<u16>,
+
+    /// Units: degrees/sec^2, 0 – disabled
+    #[kind(payload)]
+    pub acc_limiter: RollPitchYaw<u16>,
+
+    #[kind(payload)]
+    pub pid_gain: RollPitchYaw<u8>,
+
+    /// Units: Hz
+    #[kind(raw)]
+    pub frame_imu_lpf_freq: u8,
+
+    #[kind(raw)]
+    pub auto_pid_cfg: u8,
+
+    #[kind(raw)]
+    pub auto_pid_gain: u8,
+
+    /// Units: 1 degree
+    #[kind(payload)]
+    pub frame_cam_angle_min: RollPitchYaw<i16>,
+
+    /// Units: 1 degree
+    #[kind(payload)]
+    pub frame_cam_angle_max: RollPitchYaw<i16>,
+
+    #[kind(raw)]
+    pub general_flags2: u16,
+
+    #[kind(raw)]
+    pub auto_speed: u8,
+
+    #[kind(raw)]
+    pub auto_acc_limiter: u8,
+
+    /// Correction of the IMU mounting angles
+    /// Units: 0.01 degree
+    #[kind(payload)]
+    pub imu_orientation_corr: RollPitchYaw<i16>,
+
+    /// Units: seconds
+    #[kind(raw)]
+    pub timelapse_time: u16,
+
+    /// Units: ms
+    #[kind(raw)]
+    pub emergency_stop_restart_delay: u16,
+
+    #[kind(raw)]
+    pub timelapse_acc_part: u8,
+
+    #[kind(payload)]
+    pub momentum: RollPitchYaw<u16>,
+
+    #[kind(payload)]
+    pub momentum_calib_stimulus: RollPitchYaw<u8>,
+
+    #[kind(payload)]
+    pub momentum_ellipticity: RollPitchYaw<u8>,
+
+    #[kind(payload)]
+    pub follow_range: RollPitchYaw<u8>,
+
+    #[kind(payload)]
+    pub stab_axis: RollPitchYaw<u8>,
+
+    /// Units: 1 degree
+    #[kind(raw)]
+    pub outer_mot_tilt_angle: i8,
+
+    #[kind(raw)]
+    pub startup_action: [u8; 4],
+
+    #[kind(raw)]
+    pub startup_action_src: [u8; 4],
+
+    /// Signed values
+    #[kind(raw)]
+    pub startup_action_threshold: [u8; 4],
+
+    #[kind(payload)]
+    pub force_position_cfg: RollPitchYaw<u8>,
+
+    #[kind(raw)]
+    pub step_signal_src: [u8; 6],
+
+    #[kind(raw)]
+    pub step_signal_cfg: [u8; 6],
+
+    #[kind(raw)]
+    pub rc_calib_src: u8,
+
+    #[kind(raw)]
+    pub rc_calib_gain: u8,
+
+    #[kind(raw)]
+    pub parking_pos_cfg: u8,
+
+    #[kind(raw)]
+    pub ext_led_pin_id: u8,
+
+    #[kind(raw)]
+    pub interrupt_cfg: u16,
+
+    /// Units: 0.1 sec
+    #[kind(raw)]
+    pub overload_time: u8,
+
+    #[kind(raw)]
+    pub auto_pid_momentum: u8,
+
+    #[kind(payload)]
+    pub jerk_slope: RollPitchYaw<u8>,
+
+    /// (frw. ver. 2.68+)
+    #[kind(raw)]
+    #[optional]
+    pub mav_ctrl_mode: Option<u8>,
+
+    /// (frw. ver. 2.68+)
+    #[kind(raw)]
+    #[optional]
+    pub rc_deadband_pitch: Option<u8>,
+
+    /// (frw. ver. 2.68+)
+    #[kind(raw)]
+    #[optional]
+    pub rc_expo_rate_pitch: Option<u8>,
+}
+
+/// Extended parameters, part 3.
+/// (frw. ver. 2.66+)
+#[derive(BgcPayload, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParamsExt3Data {
+    /// profile ID to read or write. To access current (active) profile,
+    /// specify 255. Possible values: 0..4
+    #[kind(raw)]
+    pub profile_id: u8,
+
+    #[kind(raw)]
+    pub reserved1: [u8; 21],
+
+    #[kind(raw)]
+    pub ext_imu_type: u8,
+
+    #[kind(raw)]
+    pub ext_imu_port: u8,
+
+    #[kind(raw)]
+    pub ext_imu_position: u8,
+
+    #[kind(raw)]
+    pub ext_imu_orientation: u8,
+
+    #[kind(raw)]
+    pub ext_imu_flags: u16,
+
+    #[kind(raw)]
+    pub ext_imu_reserved: [u8; 12],
+
+    /// Units: 0.1 degree
+    #[kind(payload)]
+    pub soft_limit_width: RollPitchYaw<u8>,
+
+    #[kind(raw)]
+    pub adc_replace_src: [u8; 3],
+
+    #[kind(raw)]
+    pub glock_mid_motor_pos_corr_rate: u8,
+
+    #[kind(raw)]
+    pub extra_btn_cfg: [u8; 5],
+
+    #[kind(raw)]
+    pub can_imu_ext_sens_type: u8,
+
+    #[kind(raw)]
+    pub profile_flags2: u16,
+
+    #[kind(raw)]
+    pub reserved2: [u8; 3],
+
+    #[kind(raw)]
+    pub general_flags3: u16,
+
+    /// Order of Euler angles used by this profile
+    /// (frw. ver. 2.68+)
+    #[kind(enumeration)]
+    #[format(u8)]
+    #[optional]
+    pub euler_order: Option<EulerOrder>,
+
+    /// Second battery threshold, used to warn before the alarm. Negative value
+    /// means function is disabled.
+    /// Units: 0.01 volt
+    /// (frw. ver. 2.68+)
+    #[kind(raw)]
+    #[optional]
+    pub bat_threshold_warn: Option<i16>,
+
+    /// Battery voltage at which the controller shuts down. Negative value
+    /// means function is disabled.
+    /// Units: 0.01 volt
+    /// (frw. ver. 2.68+)
+    #[kind(raw)]
+    #[optional]
+    pub bat_threshold_shutdown: Option<i16>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use bytes::Bytes;
+
+    #[test]
+    fn params_ext() {
+        let mut b: Vec<u8> = (0..104).collect();
+        // FILTERS_EN
+        b[25..28].copy_from_slice(&[0b0001, 0b1000, 0b1111]);
+        let params = ParamsExtData::from_bytes(Bytes::from(b.clone())).unwrap();
+        assert_eq!(params.profile_id, 0);
+        assert_eq!(params.notch.roll.notch_freq, [1, 2, 3]);
+        assert_eq!(params.lpf_freq.roll, u16::from_le_bytes([19, 20]));
+        assert_eq!(params.filters_en.pitch, FilterFlags::Lpf);
+        assert_eq!(params.d_term_lpf_freq.yaw, 103);
+        assert_eq!(&params.to_bytes()[..], &b[..]);
+
+        b[25] = 0xFF;
+        assert_eq!(
+            ParamsExtData::from_bytes(Bytes::from(b)),
+            Err(PayloadParseError::InvalidFlags {
+                name: std::any::type_name::<FilterFlags>().into()
+            })
+        );
+
+        // older firmware does not send the trailing fields of EXT2
+        let size = <ParamsExt2Data as PayloadSize>::MIN_SIZE;
+        let b = Bytes::from(vec![0; size]);
+        let params = ParamsExt2Data::from_bytes(b.clone()).unwrap();
+        assert_eq!(params.rc_expo_rate_pitch, None);
+        assert_eq!(params.to_bytes(), b);
+
+        let b = Bytes::from(vec![0; <ParamsExt3Data as PayloadSize>::SIZE]);
+        let params = ParamsExt3Data::from_bytes(b.clone()).unwrap();
+        assert_eq!(params.euler_order, Some(EulerOrder::PitchRollYaw));
+        assert_eq!(params.to_bytes(), b);
+    }
+}
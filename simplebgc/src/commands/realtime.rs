@@ -4,12 +4,14 @@ use enumflags2::{BitFlags, bitflags};
 
 #[bitflags]
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum RTDataFlags {
     MotorsOn = 1 << 0,
 }
 
 #[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AccGyroData {
     #[kind(raw)]
     pub acc_data: i16,
@@ -18,12 +20,13 @@ pub struct AccGyroData {
     pub gyro_data: i16,
 }
 
-payload_rpy!(AccGyroData, 4);
+payload_rpy!(AccGyroData);
 
 #[derive(BgcPayload, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[size(63)]
 pub struct RealtimeData3 {
     #[kind(payload)]
-    #[size(12)]
     pub acc_gyro_data: RollPitchYaw<AccGyroData>,
 
     #[kind(raw)]
@@ -38,30 +41,32 @@ pub struct RealtimeData3 {
     #[kind(raw)]
     pub reserved: [u8; 3],
 
+    /// RC control channels values (PWM or normalized analog)
     #[kind(payload)]
-    #[size(6)]
-    pub rc_rpy: RollPitchYaw<i16>,
+    pub rc_rpy: RollPitchYaw<RcSignal>,
 
-    #[kind(raw)]
-    pub rc_cmd: i16,
+    /// RC command channel value (PWM or normalized analog)
+    #[kind(payload)]
+    pub rc_cmd: RcSignal,
 
-    #[kind(raw)]
-    pub ext_fc_roll: i16,
+    /// External FC roll and pitch angles
+    #[kind(payload)]
+    pub ext_fc_roll: Angle14,
 
-    #[kind(raw)]
-    pub ext_fc_pitch: i16,
+    #[kind(payload)]
+    pub ext_fc_pitch: Angle14,
 
+    /// Camera angles in 14-bit resolution per full turn
     #[kind(payload)]
-    #[size(6)]
-    pub imu_angle: RollPitchYaw<i16>,
+    pub imu_angle: RollPitchYaw<Angle14>,
 
+    /// Angles measured by the second IMU (if present) in 14-bit resolution per full turn
     #[kind(payload)]
-    #[size(6)]
-    pub frame_imu_angle: RollPitchYaw<i16>,
+    pub frame_imu_angle: RollPitchYaw<Angle14>,
 
+    /// Target angles in 14-bit resolution per full turn
     #[kind(payload)]
-    #[size(6)]
-    pub target_angle: RollPitchYaw<i16>,
+    pub target_angle: RollPitchYaw<Angle14>,
 
     #[kind(raw)]
     pub cycle_time: u16,
@@ -77,6 +82,7 @@ pub struct RealtimeData3 {
 
     #[kind(flags)]
     #[format(u8)]
+    #[cfg_attr(feature = "serde", serde(with = "crate::flag_names"))]
     pub rt_data_flags: BitFlags<RTDataFlags>,
 
     #[kind(raw)]
@@ -86,6 +92,109 @@ pub struct RealtimeData3 {
     pub cur_profile: u8,
 
     #[kind(payload)]
-    #[size(3)]
     pub motor_power: RollPitchYaw<u8>,
 }
+
+/// (frw. ver. 2.40+)
+#[derive(BgcPayload, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[size(124)]
+pub struct RealtimeData4 {
+    /// All of the fields of `CMD_REALTIME_DATA_3`
+    #[kind(payload)]
+    pub realtime_data3: RealtimeData3,
+
+    /// Relative angle of each motor between the frame and the camera,
+    /// in 14-bit resolution per full turn
+    #[kind(payload)]
+    pub frame_cam_angle: RollPitchYaw<Angle14>,
+
+    #[kind(raw)]
+    pub reserved1: u8,
+
+    /// Error in balance. Ranges: -512..512,
+    /// 0 means perfect balance.
+    #[kind(payload)]
+    pub balance_error: RollPitchYaw<i16>,
+
+    /// Actual current consumption
+    /// Units: mA
+    #[kind(raw)]
+    pub current: u16,
+
+    /// Magnetometer sensor data (with calibration)
+    #[kind(payload)]
+    pub mag_data: RollPitchYaw<i16>,
+
+    /// Temperature of the main IMU sensor
+    /// Units: Celsius
+    #[kind(raw)]
+    pub imu_temperature: i8,
+
+    /// Temperature of the frame IMU sensor
+    /// Units: Celsius
+    #[kind(raw)]
+    pub frame_imu_temperature: i8,
+
+    /// Error between the estimated gravity vector and the reference vector
+    /// for the main IMU
+    /// Units: 0.1 degree
+    #[kind(raw)]
+    pub imu_g_err: u8,
+
+    /// Error between the estimated heading vector and the reference vector
+    /// for the main IMU
+    /// Units: 0.1 degree
+    #[kind(raw)]
+    pub imu_h_err: u8,
+
+    /// Motor output. Ranges: -10000..10000
+    #[kind(payload)]
+    pub motor_out: RollPitchYaw<i16>,
+
+    /// Calibration that is currently running, 0 if none.
+    /// (frw. ver. 2.66b6+)
+    #[kind(raw)]
+    #[optional]
+    pub calib_mode: Option<u8>,
+
+    /// Error code from the CAN IMU external sensor
+    /// (frw. ver. 2.68b7+)
+    #[kind(raw)]
+    #[optional]
+    pub can_imu_ext_sens_err: Option<u8>,
+
+    #[kind(raw)]
+    #[optional]
+    pub reserved2: Option<[u8; 28]>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use bytes::Bytes;
+
+    #[test]
+    fn realtime_data4() {
+        let mut b = vec![0u8; 124];
+        // CURRENT
+        b[76..78].copy_from_slice(&1500u16.to_le_bytes());
+        // IMU_TEMPERATURE, FRAME_IMU_TEMPERATURE
+        b[84] = 45;
+        b[85] = -5i8 as u8;
+        // CALIB_MODE
+        b[94] = 1;
+
+        let data = RealtimeData4::from_bytes(Bytes::from(b.clone())).unwrap();
+        assert_eq!(data.current, 1500);
+        assert_eq!(data.imu_temperature, 45);
+        assert_eq!(data.frame_imu_temperature, -5);
+        assert_eq!(data.calib_mode, Some(1));
+        assert_eq!(&data.to_bytes()[..], &b[..]);
+
+        // firmware before 2.66b6 does not send the trailing fields
+        let data = RealtimeData4::from_bytes(Bytes::from(b[..94].to_vec())).unwrap();
+        assert_eq!(data.current, 1500);
+        assert_eq!(data.calib_mode, None);
+    }
+}
@@ -0,0 +1,403 @@
+use crate::payload::ensure_remaining;
+use crate::*;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use enumflags2::{bitflags, BitFlags};
+
+/// Data blocks that can be requested with `CMD_REALTIME_DATA_CUSTOM`.
+/// Blocks are sent in the order of their bits.
+#[bitflags]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u32)]
+pub enum RealtimeDataCustomFlags {
+    /// Main IMU angles, 3 x 2s.
+    /// Units: 0,02197265625 degree
+    ImuAngles = 1 << 0,
+    /// Target angles, 3 x 2s.
+    /// Units: 0,02197265625 degree
+    TargetAngles = 1 << 1,
+    /// Target speed, 3 x 2s.
+    /// Units: 0,06103701895 degree/sec
+    TargetSpeed = 1 << 2,
+    /// Relative angle of each motor between stator and rotor, 3 x 2s.
+    /// Units: 0,02197265625 degree
+    StatorRotorAngle = 1 << 3,
+    /// Data from the gyroscope sensor with the calibrations applied, 3 x 2s.
+    /// Units: 0,06103701895 degree/sec
+    GyroData = 1 << 4,
+    /// RC_ROLL, RC_PITCH, RC_YAW, RC_CMD, FC_ROLL, FC_PITCH, 6 x 2s.
+    RcData = 1 << 5,
+    /// Z and H vectors in the frame of the main IMU, 3 x 4f each.
+    ZVectorHVector = 1 << 6,
+    /// RC channels in the normalized range -16384..16384, 18 x 2s.
+    RcChannels = 1 << 7,
+    /// Data from the accelerometer sensor with the calibrations applied, 3 x 2s.
+    /// Units: 1/512 G
+    AccData = 1 << 8,
+    /// Motor4 control data, 8 bytes.
+    /// (frw. ver. 2.68b7+)
+    Motor4Control = 1 << 9,
+    /// Extended AHRS debug information, 26 bytes.
+    AhrsDebugInfo = 1 << 10,
+    /// Raw encoder angles in 24-bit resolution, 3 x 3s.
+    EncoderRaw24 = 1 << 11,
+    /// Main IMU angles in radians, 3 x 4f.
+    ImuAnglesRad = 1 << 12,
+    /// Float script variables, 10 x 4f.
+    ScriptVarsFloat = 1 << 13,
+    /// Integer script variables, 10 x 2s.
+    ScriptVarsInt16 = 1 << 14,
+}
+
+/// Request configurable realtime data.
+/// (frw. ver. 2.60+)
+#[derive(BgcPayload, Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[size(10)]
+pub struct RealtimeDataCustomQuery {
+    #[kind(flags)]
+    #[format(u32)]
+    #[name("FLAGS")]
+    #[cfg_attr(feature = "serde", serde(with = "crate::flag_names"))]
+    pub flags: BitFlags<RealtimeDataCustomFlags>,
+
+    #[kind(raw)]
+    #[name("RESERVED")]
+    pub reserved: [u8; 6],
+}
+
+impl RealtimeDataCustomQuery {
+    pub fn new(flags: BitFlags<RealtimeDataCustomFlags>) -> Self {
+        RealtimeDataCustomQuery {
+            flags,
+            reserved: [0; 6],
+        }
+    }
+}
+
+impl DataStreamInterval {
+    /// Sends `CMD_REALTIME_DATA_CUSTOM` with the blocks given by `flags`
+    /// every `interval_ms` milliseconds.
+    pub fn realtime_data_custom(
+        flags: BitFlags<RealtimeDataCustomFlags>,
+        interval_ms: u16,
+    ) -> Self {
+        let mut interval = Self::new(StreamCommand::RealtimeDataCustom, interval_ms);
+        interval.config[..4].copy_from_slice(&flags.bits().to_le_bytes());
+        interval
+    }
+}
+
+/// `CMD_REALTIME_DATA_CUSTOM` as it is received. The data blocks can only be
+/// decoded with [`RealtimeDataCustomRaw::decode`], because the reply does not
+/// say which blocks were requested.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RealtimeDataCustomRaw {
+    /// Units: milliseconds
+    pub timestamp_ms: u16,
+    pub data: Bytes,
+}
+
+impl Payload for RealtimeDataCustomRaw {
+    fn from_bytes(mut b: Bytes) -> Result<Self, PayloadParseError>
+    where
+        Self: Sized,
+    {
+        ensure_remaining(&b, "TIMESTAMP_MS", 2)?;
+        Ok(RealtimeDataCustomRaw {
+            timestamp_ms: b.get_u16_le(),
+            data: b,
+        })
+    }
+
+    fn to_bytes(&self) -> Bytes
+    where
+        Self: Sized,
+    {
+        let mut b = BytesMut::with_capacity(2 + self.data.len());
+        b.put_u16_le(self.timestamp_ms);
+        b.put(self.data.clone());
+        b.freeze()
+    }
+}
+
+impl RealtimeDataCustomRaw {
+    /// Decodes the data blocks given by `flags`, which must be the flags that
+    /// were used to request this data.
+    pub fn decode(
+        &self,
+        flags: BitFlags<RealtimeDataCustomFlags>,
+    ) -> Result<RealtimeDataCustom, PayloadParseError> {
+        use RealtimeDataCustomFlags::*;
+
+        let mut b = self.data.clone();
+
+        macro_rules! block {
+            ($flag: ident, $name: literal, $size: expr, $read: expr) => {
+                if flags.contains($flag) {
+                    ensure_remaining(&b, $name, $size)?;
+                    Some($read(&mut b))
+                } else {
+                    None
+                }
+            };
+        }
+
+        let rpy_i16 = |b: &mut Bytes| RollPitchYaw {
+            roll: b.get_i16_le(),
+            pitch: b.get_i16_le(),
+            yaw: b.get_i16_le(),
+        };
+        let rpy_angle = |b: &mut Bytes| rpy_i16(b).map(Angle14);
+        let vector = |b: &mut Bytes| [b.get_f32_le(), b.get_f32_le(), b.get_f32_le()];
+        // sign extend from 24 bits
+        let int24 = |b: &mut Bytes| ((b.get_uint_le(3) as i32) << 8) >> 8;
+
+        Ok(RealtimeDataCustom {
+            timestamp_ms: self.timestamp_ms,
+            imu_angles: block!(ImuAngles, "IMU_ANGLES", 6, rpy_angle),
+            target_angles: block!(TargetAngles, "TARGET_ANGLES", 6, rpy_angle),
+            target_speed: block!(TargetSpeed, "TARGET_SPEED", 6, rpy_i16),
+            stator_rotor_angle: block!(StatorRotorAngle, "STATOR_ROTOR_ANGLE", 6, rpy_angle),
+            gyro_data: block!(GyroData, "GYRO_DATA", 6, rpy_i16),
+            rc_data: block!(RcData, "RC_DATA", 12, |b: &mut Bytes| CustomRcData {
+                rc: rpy_i16(b).map(RcSignal),
+                rc_cmd: RcSignal(b.get_i16_le()),
+                fc_roll: Angle14(b.get_i16_le()),
+                fc_pitch: Angle14(b.get_i16_le()),
+            }),
+            z_vector_h_vector: block!(ZVectorHVector, "Z_VECTOR,H_VECTOR", 24, |b: &mut Bytes| {
+                (vector(b), vector(b))
+            }),
+            rc_channels: block!(RcChannels, "RC_CHANNELS", 36, |b: &mut Bytes| {
+                let mut channels = [HighResRcSignal(0); 18];
+                for channel in channels.iter_mut() {
+                    *channel = HighResRcSignal(b.get_i16_le());
+                }
+                channels
+            }),
+            acc_data: block!(AccData, "ACC_DATA", 6, rpy_i16),
+            motor4_control: block!(Motor4Control, "MOTOR4_CONTROL", 8, |b: &mut Bytes| {
+                Motor4ControlData {
+                    ff_speed: b.get_i16_le(),
+                    angle_error: b.get_i16_le(),
+                    pid_out: b.get_f32_le(),
+                }
+            }),
+            ahrs_debug_info: block!(AhrsDebugInfo, "AHRS_DEBUG_INFO", 26, |b: &mut Bytes| {
+                let mut info = [0; 26];
+                b.copy_to_slice(&mut info);
+                info
+            }),
+            encoder_raw24: block!(EncoderRaw24, "ENCODER_RAW24", 9, |b: &mut Bytes| {
+                RollPitchYaw {
+                    roll: int24(b),
+                    pitch: int24(b),
+                    yaw: int24(b),
+                }
+            }),
+            imu_angles_rad: block!(ImuAnglesRad, "IMU_ANGLES_RAD", 12, |b| {
+                let [roll, pitch, yaw] = vector(b);
+                RollPitchYaw { roll, pitch, yaw }
+            }),
+            script_vars_float: block!(ScriptVarsFloat, "SCRIPT_VARS_FLOAT", 40, |b: &mut Bytes| {
+                let mut vars = [0.0; 10];
+                for var in vars.iter_mut() {
+                    *var = b.get_f32_le();
+                }
+                vars
+            }),
+            script_vars_int16: block!(ScriptVarsInt16, "SCRIPT_VARS_INT16", 20, |b: &mut Bytes| {
+                let mut vars = [0; 10];
+                for var in vars.iter_mut() {
+                    *var = b.get_i16_le();
+                }
+                vars
+            }),
+        })
+    }
+}
+
+/// `RC_DATA` block of `CMD_REALTIME_DATA_CUSTOM`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CustomRcData {
+    pub rc: RollPitchYaw<RcSignal>,
+    pub rc_cmd: RcSignal,
+    pub fc_roll: Angle14,
+    pub fc_pitch: Angle14,
+}
+
+/// `MOTOR4_CONTROL` block of `CMD_REALTIME_DATA_CUSTOM`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Motor4ControlData {
+    pub ff_speed: i16,
+    pub angle_error: i16,
+    pub pid_out: f32,
+}
+
+/// Decoded `CMD_REALTIME_DATA_CUSTOM`. Blocks that were not requested are
+/// `None`.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RealtimeDataCustom {
+    /// Units: milliseconds
+    pub timestamp_ms: u16,
+    pub imu_angles: Option<RollPitchYaw<Angle14>>,
+    pub target_angles: Option<RollPitchYaw<Angle14>>,
+    /// Units: 0,06103701895 degree/sec
+    pub target_speed: Option<RollPitchYaw<i16>>,
+    pub stator_rotor_angle: Option<RollPitchYaw<Angle14>>,
+    /// Units: 0,06103701895 degree/sec
+    pub gyro_data: Option<RollPitchYaw<i16>>,
+    pub rc_data: Option<CustomRcData>,
+    pub z_vector_h_vector: Option<([f32; 3], [f32; 3])>,
+    pub rc_channels: Option<[HighResRcSignal; 18]>,
+    /// Units: 1/512 G
+    pub acc_data: Option<RollPitchYaw<i16>>,
+    pub motor4_control: Option<Motor4ControlData>,
+    pub ahrs_debug_info: Option<[u8; 26]>,
+    pub encoder_raw24: Option<RollPitchYaw<i32>>,
+    /// Units: radians
+    pub imu_angles_rad: Option<RollPitchYaw<f32>>,
+    pub script_vars_float: Option<[f32; 10]>,
+    pub script_vars_int16: Option<[i16; 10]>,
+}
+
+impl RealtimeDataCustom {
+    /// Returns the flags of the blocks that are present.
+    pub fn flags(&self) -> BitFlags<RealtimeDataCustomFlags> {
+        use RealtimeDataCustomFlags::*;
+
+        let mut flags = BitFlags::empty();
+        let mut set = |present: bool, flag| {
+            if present {
+                flags |= flag;
+            }
+        };
+
+        set(self.imu_angles.is_some(), ImuAngles);
+        set(self.target_angles.is_some(), TargetAngles);
+        set(self.target_speed.is_some(), TargetSpeed);
+        set(self.stator_rotor_angle.is_some(), StatorRotorAngle);
+        set(self.gyro_data.is_some(), GyroData);
+        set(self.rc_data.is_some(), RcData);
+        set(self.z_vector_h_vector.is_some(), ZVectorHVector);
+        set(self.rc_channels.is_some(), RcChannels);
+        set(self.acc_data.is_some(), AccData);
+        set(self.motor4_control.is_some(), Motor4Control);
+        set(self.ahrs_debug_info.is_some(), AhrsDebugInfo);
+        set(self.encoder_raw24.is_some(), EncoderRaw24);
+        set(self.imu_angles_rad.is_some(), ImuAnglesRad);
+        set(self.script_vars_float.is_some(), ScriptVarsFloat);
+        set(self.script_vars_int16.is_some(), ScriptVarsInt16);
+
+        flags
+    }
+
+    /// Encodes the blocks that are present, in the order the controller sends
+    /// them.
+    pub fn to_raw(&self) -> RealtimeDataCustomRaw {
+        let mut b = BytesMut::new();
+
+        let put_rpy_i16 = |b: &mut BytesMut, rpy: &RollPitchYaw<i16>| {
+            b.put_i16_le(rpy.roll);
+            b.put_i16_le(rpy.pitch);
+            b.put_i16_le(rpy.yaw);
+        };
+        let put_rpy_angle = |b: &mut BytesMut, rpy: &RollPitchYaw<Angle14>| {
+            put_rpy_i16(b, &rpy.map(|a| a.0))
+        };
+        let put_vector = |b: &mut BytesMut, v: &[f32; 3]| v.iter().for_each(|x| b.put_f32_le(*x));
+
+        if let Some(v) = &self.imu_angles {
+            put_rpy_angle(&mut b, v);
+        }
+        if let Some(v) = &self.target_angles {
+            put_rpy_angle(&mut b, v);
+        }
+        if let Some(v) = &self.target_speed {
+            put_rpy_i16(&mut b, v);
+        }
+        if let Some(v) = &self.stator_rotor_angle {
+            put_rpy_angle(&mut b, v);
+        }
+        if let Some(v) = &self.gyro_data {
+            put_rpy_i16(&mut b, v);
+        }
+        if let Some(v) = &self.rc_data {
+            put_rpy_i16(&mut b, &v.rc.map(|s| s.0));
+            b.put_i16_le(v.rc_cmd.0);
+            b.put_i16_le(v.fc_roll.0);
+            b.put_i16_le(v.fc_pitch.0);
+        }
+        if let Some((z, h)) = &self.z_vector_h_vector {
+            put_vector(&mut b, z);
+            put_vector(&mut b, h);
+        }
+        if let Some(v) = &self.rc_channels {
+            v.iter().for_each(|s| b.put_i16_le(s.0));
+        }
+        if let Some(v) = &self.acc_data {
+            put_rpy_i16(&mut b, v);
+        }
+        if let Some(v) = &self.motor4_control {
+            b.put_i16_le(v.ff_speed);
+            b.put_i16_le(v.angle_error);
+            b.put_f32_le(v.pid_out);
+        }
+        if let Some(v) = &self.ahrs_debug_info {
+            b.put_slice(v);
+        }
+        if let Some(v) = &self.encoder_raw24 {
+            b.put_int_le(v.roll as i64, 3);
+            b.put_int_le(v.pitch as i64, 3);
+            b.put_int_le(v.yaw as i64, 3);
+        }
+        if let Some(v) = &self.imu_angles_rad {
+            put_vector(&mut b, &[v.roll, v.pitch, v.yaw]);
+        }
+        if let Some(v) = &self.script_vars_float {
+            v.iter().for_each(|x| b.put_f32_le(*x));
+        }
+        if let Some(v) = &self.script_vars_int16 {
+            v.iter().for_each(|x| b.put_i16_le(*x));
+        }
+
+        RealtimeDataCustomRaw {
+            timestamp_ms: self.timestamp_ms,
+            data: b.freeze(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn realtime_data_custom() {
+        use RealtimeDataCustomFlags::*;
+
+        let data = RealtimeDataCustom {
+            timestamp_ms: 1000,
+            imu_angles: Some(RollPitchYaw::from((Angle14(1), Angle14(-2), Angle14(3)))),
+            encoder_raw24: Some(RollPitchYaw::from((-1, 0x7FFFFF, -0x800000))),
+            imu_angles_rad: Some(RollPitchYaw::from((0.5, -0.25, 1.0))),
+            ..Default::default()
+        };
+        let flags = ImuAngles | EncoderRaw24 | ImuAnglesRad;
+        assert_eq!(data.flags(), flags);
+
+        let raw = data.to_raw();
+        assert_eq!(raw.data.len(), 6 + 9 + 12);
+
+        let raw = RealtimeDataCustomRaw::from_bytes(raw.to_bytes()).unwrap();
+        assert_eq!(raw.decode(flags), Ok(data));
+
+        // decoding with a block that was not sent fails instead of reading garbage
+        assert!(raw.decode(flags | ScriptVarsFloat).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+use crate::payload::ensure_remaining;
+use crate::*;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+/// Number of virtual channels, which are used as inputs when RC_VIRT_MODE is
+/// `RcVirtMode::API`.
+pub const VIRT_CH_COUNT: usize = 32;
+
+/// Value of a channel in `CMD_API_VIRT_CH_CONTROL` that has no signal.
+pub const VIRT_CH_NO_SIGNAL: i16 = -10000;
+
+/// Value of a channel in `CMD_API_VIRT_CH_HIGH_RES` that has no signal.
+pub const VIRT_CH_HIGH_RES_NO_SIGNAL: i16 = -32768;
+
+/// Sets the value of every virtual channel, where `None` means there is no
+/// signal on the channel.
+/// Units: -500..500
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VirtChControl {
+    pub channels: [Option<i16>; VIRT_CH_COUNT],
+}
+
+impl Payload for VirtChControl {
+    fn from_bytes(mut b: Bytes) -> Result<Self, PayloadParseError>
+    where
+        Self: Sized,
+    {
+        ensure_remaining(&b, "VIRT_CH", VIRT_CH_COUNT * 2)?;
+        let mut channels = [None; VIRT_CH_COUNT];
+        for channel in channels.iter_mut() {
+            let value = b.get_i16_le();
+            *channel = Some(value).filter(|v| *v != VIRT_CH_NO_SIGNAL);
+        }
+        Ok(VirtChControl { channels })
+    }
+
+    fn to_bytes(&self) -> Bytes
+    where
+        Self: Sized,
+    {
+        let mut b = BytesMut::with_capacity(VIRT_CH_COUNT * 2);
+        for channel in &self.channels {
+            b.put_i16_le(channel.unwrap_or(VIRT_CH_NO_SIGNAL));
+        }
+        b.freeze()
+    }
+}
+
+impl PayloadSize for VirtChControl {
+    const SIZE: usize = VIRT_CH_COUNT * 2;
+}
+
+/// Sets the value of the first virtual channels with a higher resolution,
+/// where `None` means there is no signal on the channel. Channels after the
+/// last one given are not changed.
+/// Units: -16384..16384
+/// (frw. ver. 2.70b1+)
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VirtChHighRes {
+    pub channels: Vec<Option<i16>>,
+}
+
+impl Payload for VirtChHighRes {
+    fn from_bytes(mut b: Bytes) -> Result<Self, PayloadParseError>
+    where
+        Self: Sized,
+    {
+        let count = (b.remaining() / 2).min(VIRT_CH_COUNT);
+        let channels = (0..count)
+            .map(|_| Some(b.get_i16_le()).filter(|v| *v != VIRT_CH_HIGH_RES_NO_SIGNAL))
+            .collect();
+        Ok(VirtChHighRes { channels })
+    }
+
+    fn to_bytes(&self) -> Bytes
+    where
+        Self: Sized,
+    {
+        let mut b = BytesMut::with_capacity(self.channels.len() * 2);
+        for channel in self.channels.iter().take(VIRT_CH_COUNT) {
+            b.put_i16_le(channel.unwrap_or(VIRT_CH_HIGH_RES_NO_SIGNAL));
+        }
+        b.freeze()
+    }
+}
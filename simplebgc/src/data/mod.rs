@@ -1,4 +1,6 @@
 #[macro_use]
 mod rpy;
+mod units;
 
 pub use rpy::*;
+pub use units::*;
@@ -1,4 +1,5 @@
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RollPitchYaw<T> {
     pub roll: T,
     pub pitch: T,
@@ -53,9 +54,9 @@ impl<T> RollPitchYaw<T> {
     }
 }
 
-impl<T> Into<(T, T, T)> for RollPitchYaw<T> {
-    fn into(self) -> (T, T, T) {
-        (self.roll, self.pitch, self.yaw)
+impl<T> From<RollPitchYaw<T>> for (T, T, T) {
+    fn from(rpy: RollPitchYaw<T>) -> Self {
+        (rpy.roll, rpy.pitch, rpy.yaw)
     }
 }
 
@@ -71,18 +72,25 @@ impl<T> From<(T, T, T)> for RollPitchYaw<T> {
 
 impl<T: Copy> Copy for RollPitchYaw<T> {}
 
+impl<T: crate::PayloadSize> crate::PayloadSize for RollPitchYaw<T> {
+    const SIZE: usize = T::SIZE * 3;
+    const MIN_SIZE: usize = T::SIZE * 3;
+}
+
 #[macro_export]
 macro_rules! payload_rpy {
-    ($type: ty, $size: literal) => {
+    ($type: ty) => {
         impl Payload for RollPitchYaw<$type> {
             fn from_bytes(mut b: Bytes) -> Result<Self, PayloadParseError>
             where
                 Self: Sized,
             {
+                let size = <$type as PayloadSize>::SIZE;
+                $crate::payload::ensure_remaining(&b, stringify!($type), size * 3)?;
                 Ok(RollPitchYaw {
-                    roll: Payload::from_bytes(b.split_to($size))?,
-                    pitch: Payload::from_bytes(b.split_to($size))?,
-                    yaw: Payload::from_bytes(b.split_to($size))?,
+                    roll: Payload::from_bytes(b.split_to(size))?,
+                    pitch: Payload::from_bytes(b.split_to(size))?,
+                    yaw: Payload::from_bytes(b.split_to(size))?,
                 })
             }
 
@@ -90,7 +98,7 @@ macro_rules! payload_rpy {
             where
                 Self: Sized,
             {
-                let mut b = BytesMut::with_capacity($size * 3);
+                let mut b = BytesMut::with_capacity(<$type as PayloadSize>::SIZE * 3);
                 b.put(Payload::to_bytes(&self.roll));
                 b.put(Payload::to_bytes(&self.pitch));
                 b.put(Payload::to_bytes(&self.yaw));
@@ -0,0 +1,275 @@
+use crate::{
+    FieldError, FieldPath, FieldValue, Payload, PayloadFields, PayloadParseError, PayloadSize,
+    RollPitchYaw,
+};
+use bytes::{BufMut, Bytes, BytesMut};
+use std::f64::consts::PI;
+
+/// Implements `Payload`, `PayloadSize` and `PayloadFields` for a newtype around an `i16`,
+/// along with conversions to and from the raw value.
+macro_rules! payload_i16_newtype {
+    ($type: ident) => {
+        impl Payload for $type {
+            fn from_bytes(b: Bytes) -> Result<Self, PayloadParseError>
+            where
+                Self: Sized,
+            {
+                Ok($type(<i16 as Payload>::from_bytes(b)?))
+            }
+
+            fn to_bytes(&self) -> Bytes
+            where
+                Self: Sized,
+            {
+                Payload::to_bytes(&self.0)
+            }
+        }
+
+        impl PayloadSize for $type {
+            const SIZE: usize = 2;
+        }
+
+        /// Fields hold the raw value.
+        impl PayloadFields for $type {
+            fn visit_fields(&self, path: &FieldPath, visit: &mut dyn FnMut(String, FieldValue)) {
+                self.0.visit_fields(path, visit)
+            }
+
+            fn set_field(
+                &mut self,
+                path: &FieldPath,
+                name: &str,
+                value: &FieldValue,
+            ) -> Result<bool, FieldError> {
+                self.0.set_field(path, name, value)
+            }
+        }
+
+        impl From<i16> for $type {
+            fn from(raw: i16) -> Self {
+                $type(raw)
+            }
+        }
+
+        impl From<$type> for i16 {
+            fn from(value: $type) -> Self {
+                value.0
+            }
+        }
+
+        payload_rpy!($type);
+    };
+}
+
+/// An angle in 14-bit resolution per full turn.
+/// Units: 0,02197265625 degree (360 / 16384)
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Angle14(pub i16);
+
+impl Angle14 {
+    /// Number of units in one degree.
+    pub const UNITS_PER_DEGREE: f64 = 16384.0 / 360.0;
+
+    /// Wraps a raw value that is not necessarily an angle, such as the RC
+    /// signal sent in the ANGLE field of `CMD_CONTROL` in the RC modes.
+    pub fn from_raw(raw: i16) -> Self {
+        Angle14(raw)
+    }
+
+    /// Rounds `degrees` to the nearest unit, saturating at the limits of `i16`.
+    pub fn from_degrees(degrees: f64) -> Self {
+        Angle14((degrees * Self::UNITS_PER_DEGREE).round() as i16)
+    }
+
+    /// Rounds `radians` to the nearest unit, saturating at the limits of `i16`.
+    pub fn from_radians(radians: f64) -> Self {
+        Self::from_degrees(radians * 180.0 / PI)
+    }
+
+    pub fn to_degrees(self) -> f64 {
+        self.0 as f64 / Self::UNITS_PER_DEGREE
+    }
+
+    pub fn to_radians(self) -> f64 {
+        self.to_degrees() * PI / 180.0
+    }
+}
+
+payload_i16_newtype!(Angle14);
+
+/// An angular speed.
+/// Units: 0,1220740379 degree/sec, or 0.001 degree/sec if the speed is sent with
+/// [`AxisControlFlags::HighResSpeed`](crate::AxisControlFlags::HighResSpeed) set.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpeedUnits(pub i16);
+
+impl SpeedUnits {
+    /// Size of one unit in degree/sec.
+    pub const DEGREES_PER_SEC: f64 = 0.1220740379;
+
+    /// Size of one unit in degree/sec in high resolution mode.
+    pub const HIGH_RES_DEGREES_PER_SEC: f64 = 0.001;
+
+    /// Rounds `speed` (in degree/sec) to the nearest unit, saturating at the limits of `i16`.
+    pub fn from_degrees_per_sec(speed: f64) -> Self {
+        SpeedUnits((speed / Self::DEGREES_PER_SEC).round() as i16)
+    }
+
+    /// Rounds `speed` (in radian/sec) to the nearest unit, saturating at the limits of `i16`.
+    pub fn from_radians_per_sec(speed: f64) -> Self {
+        Self::from_degrees_per_sec(speed * 180.0 / PI)
+    }
+
+    /// Same as [`from_degrees_per_sec`](Self::from_degrees_per_sec), using high resolution
+    /// units.
+    pub fn from_degrees_per_sec_high_res(speed: f64) -> Self {
+        SpeedUnits((speed / Self::HIGH_RES_DEGREES_PER_SEC).round() as i16)
+    }
+
+    /// Same as [`from_radians_per_sec`](Self::from_radians_per_sec), using high resolution
+    /// units.
+    pub fn from_radians_per_sec_high_res(speed: f64) -> Self {
+        Self::from_degrees_per_sec_high_res(speed * 180.0 / PI)
+    }
+
+    pub fn to_degrees_per_sec(self) -> f64 {
+        self.0 as f64 * Self::DEGREES_PER_SEC
+    }
+
+    pub fn to_radians_per_sec(self) -> f64 {
+        self.to_degrees_per_sec() * PI / 180.0
+    }
+
+    pub fn to_degrees_per_sec_high_res(self) -> f64 {
+        self.0 as f64 * Self::HIGH_RES_DEGREES_PER_SEC
+    }
+
+    pub fn to_radians_per_sec_high_res(self) -> f64 {
+        self.to_degrees_per_sec_high_res() * PI / 180.0
+    }
+}
+
+payload_i16_newtype!(SpeedUnits);
+
+/// An RC signal. Normal working range is -500..500.
+/// A special value -10000 encodes a "signal lost" condition.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RcSignal(pub i16);
+
+impl RcSignal {
+    pub const MIN: i16 = -500;
+    pub const MAX: i16 = 500;
+    pub const LOST: RcSignal = RcSignal(-10000);
+
+    pub fn is_lost(self) -> bool {
+        self == Self::LOST
+    }
+
+    /// Returns the signal value, or `None` if the signal is lost.
+    pub fn value(self) -> Option<i16> {
+        if self.is_lost() {
+            None
+        } else {
+            Some(self.0)
+        }
+    }
+}
+
+impl From<Option<i16>> for RcSignal {
+    fn from(value: Option<i16>) -> Self {
+        value.map_or(Self::LOST, RcSignal)
+    }
+}
+
+payload_i16_newtype!(RcSignal);
+
+/// An RC signal in high resolution. Normal working range is -16384..16384.
+/// A special value -32768 encodes a "signal lost" condition.
+/// (frw. ver. 2.66b2+)
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HighResRcSignal(pub i16);
+
+impl HighResRcSignal {
+    pub const MIN: i16 = -16384;
+    pub const MAX: i16 = 16384;
+    pub const LOST: HighResRcSignal = HighResRcSignal(i16::MIN);
+
+    pub fn is_lost(self) -> bool {
+        self == Self::LOST
+    }
+
+    /// Returns the signal value, or `None` if the signal is lost.
+    pub fn value(self) -> Option<i16> {
+        if self.is_lost() {
+            None
+        } else {
+            Some(self.0)
+        }
+    }
+}
+
+impl From<Option<i16>> for HighResRcSignal {
+    fn from(value: Option<i16>) -> Self {
+        value.map_or(Self::LOST, HighResRcSignal)
+    }
+}
+
+payload_i16_newtype!(HighResRcSignal);
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn angle_conversions() {
+        assert_eq!(Angle14::from_degrees(90.0), Angle14(4096));
+        assert_eq!(Angle14::from_degrees(-180.0), Angle14(-8192));
+        assert_eq!(Angle14(4096).to_degrees(), 90.0);
+        assert_eq!(Angle14(1).to_degrees(), 0.02197265625);
+        assert_eq!(Angle14::from_radians(std::f64::consts::PI), Angle14(8192));
+
+        // converting to degrees and back never changes the value
+        for raw in i16::MIN..=i16::MAX {
+            let angle = Angle14(raw);
+            assert_eq!(Angle14::from_degrees(angle.to_degrees()), angle);
+            assert_eq!(Angle14::from_radians(angle.to_radians()), angle);
+        }
+    }
+
+    #[test]
+    fn speed_conversions() {
+        assert_eq!(SpeedUnits::from_degrees_per_sec(100.0), SpeedUnits(819));
+        assert_eq!(SpeedUnits::from_degrees_per_sec_high_res(1.5), SpeedUnits(1500));
+        assert_eq!(SpeedUnits(1500).to_degrees_per_sec_high_res(), 1.5);
+
+        for raw in i16::MIN..=i16::MAX {
+            let speed = SpeedUnits(raw);
+            assert_eq!(SpeedUnits::from_degrees_per_sec(speed.to_degrees_per_sec()), speed);
+            assert_eq!(SpeedUnits::from_radians_per_sec(speed.to_radians_per_sec()), speed);
+            assert_eq!(
+                SpeedUnits::from_degrees_per_sec_high_res(speed.to_degrees_per_sec_high_res()),
+                speed
+            );
+        }
+    }
+
+    #[test]
+    fn rc_signal_lost() {
+        assert!(RcSignal(-10000).is_lost());
+        assert_eq!(RcSignal(-500).value(), Some(-500));
+        assert_eq!(RcSignal::from(None), RcSignal::LOST);
+        assert!(HighResRcSignal(-32768).is_lost());
+        assert_eq!(HighResRcSignal(-16384).value(), Some(-16384));
+
+        let b = Payload::to_bytes(&RcSignal::LOST);
+        assert_eq!(&b[..], &[0xF0, 0xD8]);
+        assert_eq!(<RcSignal as Payload>::from_bytes(b), Ok(RcSignal::LOST));
+
+        let params = AxisControlParams::rc(SpeedUnits(0), RcSignal::LOST);
+        assert_eq!(params.angle, Angle14::from_raw(-10000));
+    }
+}
@@ -0,0 +1,608 @@
+//! Access to the fields of payloads by their names in the SimpleBGC spec.
+//!
+//! Field names are built from the `#[name]` of each field, or its name in
+//! Rust in upper case. The fields of a sub-payload are prefixed with the name
+//! of the sub-payload, unless they were given an explicit `#[name]`, and
+//! each axis of a [`RollPitchYaw`] adds a suffix. For example, the `p` field
+//! of the roll axis of `Params3Data::pid` is called `PID_P[ROLL]`, and the
+//! `EmergencyStop` flag of `Params3Data::general_flags` is called
+//! `GENERAL_FLAGS.EmergencyStop`.
+
+use crate::RollPitchYaw;
+use enumflags2::{BitFlag, BitFlags};
+use num_traits::FromPrimitive;
+use std::convert::TryFrom;
+use std::fmt::{self, Debug, Display};
+use thiserror::Error;
+
+/// The value of a single field.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(untagged))]
+pub enum FieldValue {
+    Int(i64),
+    Bool(bool),
+    /// The name of an enum variant, as it is printed by `Debug`.
+    Enum(String),
+    Bytes(Vec<u8>),
+}
+
+impl Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::Int(n) => write!(f, "{}", n),
+            FieldValue::Bool(b) => write!(f, "{}", b),
+            FieldValue::Enum(s) => write!(f, "{}", s),
+            FieldValue::Bytes(b) => write!(f, "{:?}", b),
+        }
+    }
+}
+
+/// A field that differs between two payloads.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FieldChange {
+    pub name: String,
+    /// `None` if the field is optional and the old payload does not have it.
+    pub old: Option<FieldValue>,
+    pub new: FieldValue,
+}
+
+impl Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.old {
+            Some(old) => write!(f, "{}: {} -> {}", self.name, old, self.new),
+            None => write!(f, "{}: {}", self.name, self.new),
+        }
+    }
+}
+
+#[derive(Error, Clone, Debug, PartialEq)]
+pub enum FieldError {
+    #[error("unknown field {name}")]
+    UnknownField { name: String },
+    #[error("invalid value {value:?} for field {name}")]
+    InvalidValue { name: String, value: FieldValue },
+    #[error("value {value} of field {name} is outside of {min}..={max}")]
+    OutOfRange {
+        name: String,
+        value: i64,
+        min: i64,
+        max: i64,
+    },
+    /// An optional field cannot be set while an optional field before it is
+    /// absent, because it would be written at the wrong offset.
+    #[error("field {name} cannot be set without the field {missing} before it")]
+    MissingOptional { name: String, missing: String },
+}
+
+/// The position of a payload inside its parents, which decides the names of
+/// its fields.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FieldPath {
+    prefix: String,
+    suffix: String,
+}
+
+impl FieldPath {
+    fn join(&self, name: &str, explicit: bool) -> String {
+        if explicit || self.prefix.is_empty() {
+            name.to_string()
+        } else if name.is_empty() {
+            self.prefix.clone()
+        } else {
+            format!("{}_{}", self.prefix, name)
+        }
+    }
+
+    /// Returns the full name of the field called `name` at this path.
+    /// `explicit` is true if `name` was given with `#[name]`.
+    pub fn name(&self, name: &str, explicit: bool) -> String {
+        self.join(name, explicit) + &self.suffix
+    }
+
+    /// Returns the path of a sub-payload called `name`.
+    pub fn child(&self, name: &str, explicit: bool) -> FieldPath {
+        FieldPath {
+            prefix: self.join(name, explicit),
+            suffix: self.suffix.clone(),
+        }
+    }
+
+    /// Returns the path of one element of a repeated field, such as an axis.
+    pub fn index(&self, index: &str) -> FieldPath {
+        FieldPath {
+            prefix: self.prefix.clone(),
+            suffix: format!("{}[{}]", self.suffix, index),
+        }
+    }
+}
+
+impl FieldValue {
+    fn invalid(&self, name: &str) -> FieldError {
+        FieldError::InvalidValue {
+            name: name.into(),
+            value: self.clone(),
+        }
+    }
+
+    pub fn to_int<T: TryFrom<i64>>(&self, name: &str) -> Result<T, FieldError> {
+        match self {
+            FieldValue::Int(n) => T::try_from(*n).map_err(|_| self.invalid(name)),
+            _ => Err(self.invalid(name)),
+        }
+    }
+
+    pub fn to_bool(&self, name: &str) -> Result<bool, FieldError> {
+        match self {
+            FieldValue::Bool(b) => Ok(*b),
+            _ => Err(self.invalid(name)),
+        }
+    }
+
+    /// Finds the enum value whose `Debug` output is this value, trying every
+    /// number in `min..=max`.
+    pub fn to_enum<T: FromPrimitive + Debug>(
+        &self,
+        name: &str,
+        min: i64,
+        max: i64,
+    ) -> Result<T, FieldError> {
+        match self {
+            FieldValue::Enum(s) => (min..=max)
+                .filter_map(T::from_i64)
+                .find(|v| format!("{:?}", v) == *s)
+                .ok_or_else(|| self.invalid(name)),
+            _ => Err(self.invalid(name)),
+        }
+    }
+
+    /// Checks that an integer, or every byte of an array, is in `min..=max`.
+    /// Other values are always in range.
+    pub fn check_range(&self, name: &str, min: i64, max: i64) -> Result<(), FieldError> {
+        let out_of_range = |value: i64| FieldError::OutOfRange {
+            name: name.into(),
+            value,
+            min,
+            max,
+        };
+
+        match self {
+            FieldValue::Int(n) if !(min..=max).contains(n) => Err(out_of_range(*n)),
+            FieldValue::Bytes(b) => match b.iter().find(|&&n| !(min..=max).contains(&(n as i64))) {
+                Some(&n) => Err(out_of_range(n as i64)),
+                None => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    pub fn to_array<const N: usize>(&self, name: &str) -> Result<[u8; N], FieldError> {
+        match self {
+            FieldValue::Bytes(b) => <[u8; N]>::try_from(&b[..]).map_err(|_| self.invalid(name)),
+            _ => Err(self.invalid(name)),
+        }
+    }
+}
+
+/// Gives access to the fields of a payload by their spec names.
+/// The `BgcPayload` derive implements this automatically.
+pub trait PayloadFields {
+    /// Calls `visit` with the name and value of every field of this payload,
+    /// in the order they appear in the payload. Optional fields that are
+    /// `None` are skipped.
+    fn visit_fields(&self, path: &FieldPath, visit: &mut dyn FnMut(String, FieldValue));
+
+    /// Sets the field called `name`, returning `false` if there is no such
+    /// field.
+    fn set_field(
+        &mut self,
+        path: &FieldPath,
+        name: &str,
+        value: &FieldValue,
+    ) -> Result<bool, FieldError>;
+
+    /// Adds an error to `errors` for every field whose value is not allowed by
+    /// the spec.
+    fn validate_fields(&self, _path: &FieldPath, _errors: &mut Vec<FieldError>) {}
+
+    /// Checks every field against the values allowed by the spec, returning
+    /// all of the fields that are not allowed.
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+        self.validate_fields(&FieldPath::default(), &mut errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Returns the name and value of every field of this payload.
+    fn fields(&self) -> Vec<(String, FieldValue)> {
+        let mut fields = Vec::new();
+        self.visit_fields(&FieldPath::default(), &mut |name, value| {
+            fields.push((name, value))
+        });
+        fields
+    }
+
+    /// Sets the field called `name`. An optional field can only be set once
+    /// every optional field before it is present.
+    fn set(&mut self, name: &str, value: &FieldValue) -> Result<(), FieldError> {
+        if self.set_field(&FieldPath::default(), name, value)? {
+            Ok(())
+        } else {
+            Err(FieldError::UnknownField { name: name.into() })
+        }
+    }
+
+    /// Returns the fields whose values in `other` differ from `self`, in the
+    /// order they appear in the payload. Optional fields that `other` does
+    /// not have are skipped, since they cannot be removed by a patch.
+    fn diff(&self, other: &Self) -> Vec<FieldChange>
+    where
+        Self: Sized,
+    {
+        let old = self.fields();
+        other
+            .fields()
+            .into_iter()
+            .filter_map(|(name, new)| {
+                let old = old.iter().find(|(n, _)| *n == name).map(|(_, v)| v);
+                if old == Some(&new) {
+                    None
+                } else {
+                    Some(FieldChange {
+                        old: old.cloned(),
+                        name,
+                        new,
+                    })
+                }
+            })
+            .collect()
+    }
+
+    /// Sets the new value of every change. If any of them fails, `self` is
+    /// left unchanged and all of the errors are returned. The old values are
+    /// not checked.
+    fn apply(&mut self, changes: &[FieldChange]) -> Result<(), Vec<FieldError>>
+    where
+        Self: Sized + Clone,
+    {
+        let mut patched = self.clone();
+        let errors: Vec<_> = changes
+            .iter()
+            .filter_map(|change| patched.set(&change.name, &change.new).err())
+            .collect();
+
+        if errors.is_empty() {
+            *self = patched;
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+macro_rules! fields_int {
+    ($type: ty) => {
+        impl PayloadFields for $type {
+            fn visit_fields(&self, path: &FieldPath, visit: &mut dyn FnMut(String, FieldValue)) {
+                visit(path.name("", false), FieldValue::Int(*self as i64));
+            }
+
+            fn set_field(
+                &mut self,
+                path: &FieldPath,
+                name: &str,
+                value: &FieldValue,
+            ) -> Result<bool, FieldError> {
+                if name != path.name("", false) {
+                    return Ok(false);
+                }
+
+                *self = value.to_int(name)?;
+                Ok(true)
+            }
+        }
+    };
+}
+
+fields_int!(u8);
+fields_int!(i8);
+fields_int!(u16);
+fields_int!(i16);
+
+/// Each flag is a separate boolean field, named after the flag.
+impl<T: BitFlag + Debug> PayloadFields for BitFlags<T> {
+    fn visit_fields(&self, path: &FieldPath, visit: &mut dyn FnMut(String, FieldValue)) {
+        let name = path.name("", false);
+        for flag in BitFlags::<T>::all().iter() {
+            visit(
+                format!("{}.{:?}", name, flag),
+                FieldValue::Bool(self.contains(flag)),
+            );
+        }
+    }
+
+    fn set_field(
+        &mut self,
+        path: &FieldPath,
+        name: &str,
+        value: &FieldValue,
+    ) -> Result<bool, FieldError> {
+        let prefix = path.name("", false);
+        let flag_name = match name.strip_prefix(&prefix).and_then(|n| n.strip_prefix('.')) {
+            Some(flag_name) => flag_name,
+            None => return Ok(false),
+        };
+
+        let flag = match BitFlags::<T>::all()
+            .iter()
+            .find(|flag| format!("{:?}", flag) == flag_name)
+        {
+            Some(flag) => flag,
+            None => return Ok(false),
+        };
+
+        if value.to_bool(name)? {
+            self.insert(flag);
+        } else {
+            self.remove(flag);
+        }
+        Ok(true)
+    }
+}
+
+impl<T: PayloadFields> PayloadFields for RollPitchYaw<T> {
+    fn visit_fields(&self, path: &FieldPath, visit: &mut dyn FnMut(String, FieldValue)) {
+        self.roll.visit_fields(&path.index("ROLL"), visit);
+        self.pitch.visit_fields(&path.index("PITCH"), visit);
+        self.yaw.visit_fields(&path.index("YAW"), visit);
+    }
+
+    fn set_field(
+        &mut self,
+        path: &FieldPath,
+        name: &str,
+        value: &FieldValue,
+    ) -> Result<bool, FieldError> {
+        Ok(self.roll.set_field(&path.index("ROLL"), name, value)?
+            || self.pitch.set_field(&path.index("PITCH"), name, value)?
+            || self.yaw.set_field(&path.index("YAW"), name, value)?)
+    }
+
+    fn validate_fields(&self, path: &FieldPath, errors: &mut Vec<FieldError>) {
+        self.roll.validate_fields(&path.index("ROLL"), errors);
+        self.pitch.validate_fields(&path.index("PITCH"), errors);
+        self.yaw.validate_fields(&path.index("YAW"), errors);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::*;
+
+    /// Profile parameters with every field zero, except for the ones that the
+    /// spec does not allow to be zero.
+    pub(crate) fn params3() -> Params3Data {
+        let mut b = vec![0; <Params3Data as PayloadSize>::SIZE];
+        b[70..74].copy_from_slice(&[3, 1, 3, 1]);
+        b[132] = 1;
+        let mut params = Params3Data::from_bytes(b.into()).unwrap();
+        params.servo_rate = 5;
+        params.adaptive_pid_rate = 1;
+        params
+    }
+
+    #[test]
+    fn field_names() {
+        let fields = params3().fields();
+        let get = |name: &str| fields.iter().find(|(n, _)| n == name).map(|(_, v)| v.clone());
+
+        assert_eq!(fields[0], ("PROFILE_ID".into(), FieldValue::Int(0)));
+        assert_eq!(get("PID_P[ROLL]"), Some(FieldValue::Int(0)));
+        assert_eq!(get("PID_INVERT[YAW]"), Some(FieldValue::Bool(false)));
+        assert_eq!(get("EXT_FC_GAIN[1]"), Some(FieldValue::Int(0)));
+        assert_eq!(get("RC_MODE[PITCH]"), Some(FieldValue::Enum("AngleRegular".into())));
+        assert_eq!(get("RC_MAP_YAW"), Some(FieldValue::Enum("None".into())));
+        assert_eq!(get("RC_MIX_FC_ROLL_RATE"), Some(FieldValue::Int(0)));
+        assert_eq!(get("AXIS_TOP"), Some(FieldValue::Enum("PosZ".into())));
+        assert_eq!(get("MENU_CMD_LONG"), Some(FieldValue::Enum("NoAction".into())));
+        assert_eq!(get("SERVO_OUT"), Some(FieldValue::Bytes(vec![0; 4])));
+        assert_eq!(get("GENERAL_FLAGS.EmergencyStop"), Some(FieldValue::Bool(false)));
+    }
+
+    #[test]
+    fn set_fields() {
+        let mut params = params3();
+        params.set("PID_P[PITCH]", &FieldValue::Int(42)).unwrap();
+        params.set("RC_MODE[YAW]", &FieldValue::Enum("Speed".into())).unwrap();
+        params.set("RC_MEMORY[ROLL]", &FieldValue::Int(-100)).unwrap();
+        params.set("GENERAL_FLAGS.EmergencyStop", &FieldValue::Bool(true)).unwrap();
+        params.set("MENU_CMD_2", &FieldValue::Enum("LookDown".into())).unwrap();
+        assert_eq!(params.pid.pitch.p, 42);
+        assert_eq!(params.rc.yaw.rc_mode, AxisRcMode::Speed);
+        assert_eq!(params.rc_memory.roll, Angle14(-100));
+        assert!(params.general_flags.contains(GeneralFlags::EmergencyStop));
+        assert_eq!(params.rc_cmd.menu_2, MenuCommand::LookDown);
+
+        // every field can be set to the value it was read as
+        let mut copy = params3();
+        for (name, value) in params.fields() {
+            copy.set(&name, &value).unwrap();
+        }
+        assert_eq!(copy, params);
+
+        assert_eq!(
+            params.set("PID_Q[ROLL]", &FieldValue::Int(1)),
+            Err(FieldError::UnknownField {
+                name: "PID_Q[ROLL]".into()
+            })
+        );
+        assert_eq!(
+            params.set("PID_P[ROLL]", &FieldValue::Int(256)),
+            Err(FieldError::InvalidValue {
+                name: "PID_P[ROLL]".into(),
+                value: FieldValue::Int(256)
+            })
+        );
+        assert!(params.set("AXIS_TOP", &FieldValue::Enum("PosW".into())).is_err());
+        assert!(params.set("SERVO_OUT", &FieldValue::Bytes(vec![0; 3])).is_err());
+        assert!(params.set("MENU_CMD_1", &FieldValue::Enum("Dance".into())).is_err());
+    }
+
+    #[test]
+    fn diff_and_apply() {
+        let old = params3();
+        let mut new = params3();
+        new.pid.roll.p = 12;
+        new.rc_map.yaw = RcMap::Serial { channel: 2 };
+        new.general_flags = GeneralFlags::EmergencyStop.into();
+
+        let changes = old.diff(&new);
+        assert_eq!(
+            changes,
+            vec![
+                FieldChange {
+                    name: "PID_P[ROLL]".into(),
+                    old: Some(FieldValue::Int(0)),
+                    new: FieldValue::Int(12),
+                },
+                FieldChange {
+                    name: "RC_MAP_YAW".into(),
+                    old: Some(FieldValue::Enum("None".into())),
+                    new: FieldValue::Enum("Serial { channel: 2 }".into()),
+                },
+                FieldChange {
+                    name: "GENERAL_FLAGS.EmergencyStop".into(),
+                    old: Some(FieldValue::Bool(false)),
+                    new: FieldValue::Bool(true),
+                },
+            ]
+        );
+        assert_eq!(changes[0].to_string(), "PID_P[ROLL]: 0 -> 12");
+        assert!(new.diff(&new).is_empty());
+
+        let mut patched = params3();
+        patched.apply(&changes).unwrap();
+        assert_eq!(patched, new);
+
+        // a patch with an invalid change is not applied at all
+        let mut bad = changes.clone();
+        bad.push(FieldChange {
+            name: "PID_D[YAW]".into(),
+            old: None,
+            new: FieldValue::Bool(true),
+        });
+        let mut patched = params3();
+        assert_eq!(patched.apply(&bad).unwrap_err().len(), 1);
+        assert_eq!(patched, old);
+
+        // optional fields missing from the old payload
+        let old = ParamsExt2Data::from_bytes(
+            vec![0; <ParamsExt2Data as PayloadSize>::MIN_SIZE].into(),
+        )
+        .unwrap();
+        let mut new = old.clone();
+        new.mav_ctrl_mode = Some(0);
+        new.rc_deadband_pitch = Some(5);
+        let changes = old.diff(&new);
+        assert_eq!(
+            changes,
+            vec![
+                FieldChange {
+                    name: "MAV_CTRL_MODE".into(),
+                    old: None,
+                    new: FieldValue::Int(0),
+                },
+                FieldChange {
+                    name: "RC_DEADBAND_PITCH".into(),
+                    old: None,
+                    new: FieldValue::Int(5),
+                }
+            ]
+        );
+        assert!(new.diff(&old).is_empty());
+
+        let mut patched = old.clone();
+        patched.apply(&changes).unwrap();
+        assert_eq!(patched, new);
+
+        // a later optional field cannot be set while an earlier one is missing
+        let mut patched = old.clone();
+        assert_eq!(
+            patched.apply(&changes[1..]),
+            Err(vec![FieldError::MissingOptional {
+                name: "RC_DEADBAND_PITCH".into(),
+                missing: "MAV_CTRL_MODE".into(),
+            }])
+        );
+        assert_eq!(patched, old);
+    }
+
+    #[test]
+    fn validate() {
+        let mut params = params3();
+        assert_eq!(params.validate(), Ok(()));
+
+        params.rc.pitch.rc_lpf = 17;
+        params.motor_output.yaw = 8;
+        params.ext_fc_gain = (-128, 0);
+        params.servo_out = [0, 33, 0, 0];
+        params.rc_mix.fc_roll.rc_mix_rate = 32;
+        params.rc_map.cmd = RcMap::Step { channel: 6 };
+        assert_eq!(
+            params.validate(),
+            Err(vec![
+                FieldError::OutOfRange {
+                    name: "EXT_FC_GAIN[0]".into(),
+                    value: -128,
+                    min: -127,
+                    max: 127,
+                },
+                FieldError::OutOfRange {
+                    name: "RC_LPF[PITCH]".into(),
+                    value: 17,
+                    min: 0,
+                    max: 16,
+                },
+                FieldError::InvalidValue {
+                    name: "RC_MAP_CMD".into(),
+                    value: FieldValue::Enum("Step { channel: 6 }".into()),
+                },
+                FieldError::OutOfRange {
+                    name: "RC_MIX_FC_ROLL_RATE".into(),
+                    value: 32,
+                    min: 0,
+                    max: 31,
+                },
+                FieldError::OutOfRange {
+                    name: "MOTOR_OUTPUT[YAW]".into(),
+                    value: 8,
+                    min: 0,
+                    max: 7,
+                },
+                FieldError::OutOfRange {
+                    name: "SERVO_OUT".into(),
+                    value: 33,
+                    min: 0,
+                    max: 32,
+                },
+            ])
+        );
+
+        let mut params = params3();
+        params.profile_id = 255;
+        assert_eq!(params.validate(), Ok(()));
+        params.profile_id = 9;
+        assert_eq!(
+            params.validate(),
+            Err(vec![FieldError::InvalidValue {
+                name: "PROFILE_ID".into(),
+                value: FieldValue::Int(9),
+            }])
+        );
+    }
+}
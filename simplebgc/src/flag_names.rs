@@ -0,0 +1,107 @@
+//! Serializes `BitFlags` as a list of the names of the flags that are set,
+//! for use with `#[serde(with = "flag_names")]`.
+
+use enumflags2::{BitFlag, BitFlags};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serializer};
+use std::fmt::Debug;
+
+pub fn serialize<T, S>(flags: &BitFlags<T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: BitFlag + Debug,
+    S: Serializer,
+{
+    serializer.collect_seq(flags.iter().map(|flag| format!("{:?}", flag)))
+}
+
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<BitFlags<T>, D::Error>
+where
+    T: BitFlag + Debug,
+    D: Deserializer<'de>,
+{
+    let mut flags = BitFlags::empty();
+    for name in Vec::<String>::deserialize(deserializer)? {
+        match BitFlags::<T>::all()
+            .iter()
+            .find(|flag| format!("{:?}", flag) == name)
+        {
+            Some(flag) => flags.insert(flag),
+            None => return Err(D::Error::custom(format!("unknown flag {}", name))),
+        }
+    }
+    Ok(flags)
+}
+
+/// Same as the parent module, for the flags of each axis.
+pub mod rpy {
+    use crate::RollPitchYaw;
+    use enumflags2::{BitFlag, BitFlags};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::fmt::Debug;
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(transparent)]
+    struct Flags<T: BitFlag + Debug>(#[serde(with = "super")] BitFlags<T>);
+
+    pub fn serialize<T, S>(
+        flags: &RollPitchYaw<BitFlags<T>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        T: BitFlag + Debug,
+        S: Serializer,
+    {
+        flags.map(Flags).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<RollPitchYaw<BitFlags<T>>, D::Error>
+    where
+        T: BitFlag + Debug,
+        D: Deserializer<'de>,
+    {
+        Ok(RollPitchYaw::<Flags<T>>::deserialize(deserializer)?.map(|flags| flags.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use bytes::Bytes;
+
+    #[test]
+    fn serde() {
+        let mut params = crate::fields::tests::params3();
+        params.general_flags = GeneralFlags::EmergencyStop | GeneralFlags::BlinkProfile;
+        params.rc_map.yaw = RcMap::PWM {
+            source: RcMapPwmSource::Yaw,
+        };
+        params.rc_memory.pitch = Angle14(-10);
+
+        let json = serde_json::to_value(&params).unwrap();
+        assert_eq!(
+            json["general_flags"],
+            serde_json::json!(["BlinkProfile", "EmergencyStop"])
+        );
+        assert_eq!(json["axis_top"], "PosZ");
+        assert_eq!(json["rc_map"]["yaw"], serde_json::json!({"PWM": {"source": "Yaw"}}));
+        assert_eq!(json["rc_memory"]["pitch"], -10);
+        assert_eq!(serde_json::from_value::<Params3Data>(json).unwrap(), params);
+
+        let mut b: Vec<u8> = (0..104).collect();
+        b[25..28].copy_from_slice(&[0b0001, 0b1000, 0b1111]);
+        let params = ParamsExtData::from_bytes(Bytes::from(b)).unwrap();
+        let json = serde_json::to_value(&params).unwrap();
+        assert_eq!(
+            json["filters_en"],
+            serde_json::json!({
+                "roll": ["Notch1"],
+                "pitch": ["Lpf"],
+                "yaw": ["Notch1", "Notch2", "Notch3", "Lpf"],
+            })
+        );
+        assert_eq!(serde_json::from_value::<ParamsExtData>(json).unwrap(), params);
+
+        let json = serde_json::json!(["Notch1", "Notch4"]);
+        assert!(flag_names::deserialize::<FilterFlags, _>(json).is_err());
+    }
+}
@@ -9,10 +9,20 @@ extern crate simplebgc_derive;
 mod data;
 #[macro_use]
 mod commands;
+#[cfg(feature = "backup")]
+mod backup;
+mod client;
+mod fields;
+#[cfg(feature = "serde")]
+pub mod flag_names;
 mod message;
 mod payload;
 
+#[cfg(feature = "backup")]
+pub use backup::*;
+pub use client::*;
 pub use commands::*;
 pub use data::*;
+pub use fields::*;
 pub use message::*;
 pub use payload::*;
@@ -3,15 +3,102 @@ use crate::payload::*;
 use crate::{IncomingCommand, OutgoingCommand};
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use thiserror::Error;
+use tokio::sync::mpsc;
 use tokio_util::codec::{Encoder, Decoder};
 
-pub trait SbgcCodec {}
+/// Firmware versions starting with 2.68 understand the V2 protocol.
+const V2_MIN_FIRMWARE_VERSION: u16 = 2680;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ProtocolVersion {
+    /// Frames start with `0x3E` and use an 8-bit payload checksum
+    V1,
+    /// Frames start with `0x24` and use a CRC16 checksum
+    V2,
+}
+
+impl ProtocolVersion {
+    /// Returns the newest protocol version that a controller running the
+    /// given firmware version (as reported in `BoardInfo`) understands.
+    pub fn for_firmware(firmware_version: u16) -> Self {
+        if firmware_version >= V2_MIN_FIRMWARE_VERSION {
+            ProtocolVersion::V2
+        } else {
+            ProtocolVersion::V1
+        }
+    }
+}
+
+pub trait SbgcCodec {
+    /// Returns the protocol version used to encode outgoing messages, or
+    /// `None` if it has not been determined yet.
+    fn protocol_version(&self) -> Option<ProtocolVersion>;
+}
 
 pub struct V1Codec;
 pub struct V2Codec;
 
-impl SbgcCodec for V1Codec {}
-impl SbgcCodec for V2Codec {}
+impl SbgcCodec for V1Codec {
+    fn protocol_version(&self) -> Option<ProtocolVersion> {
+        Some(ProtocolVersion::V1)
+    }
+}
+
+impl SbgcCodec for V2Codec {
+    fn protocol_version(&self) -> Option<ProtocolVersion> {
+        Some(ProtocolVersion::V2)
+    }
+}
+
+/// A codec that accepts both V1 and V2 frames and replies in whichever
+/// version the controller uses.
+///
+/// The version is taken from the first valid frame the controller sends. If
+/// that frame is a `BoardInfo`, or once a `BoardInfo` arrives later, the
+/// version is chosen from the reported firmware version instead, so that
+/// boards which support V2 are switched to it. Until a version has been
+/// detected, outgoing messages are encoded with the fallback version, which
+/// defaults to V1 since every board understands it.
+pub struct AutoCodec {
+    version: Option<ProtocolVersion>,
+    /// Set by [`AutoCodec::set_protocol_version`]; never changed by detection.
+    forced: Option<ProtocolVersion>,
+    fallback: ProtocolVersion,
+}
+
+impl AutoCodec {
+    pub fn new() -> Self {
+        AutoCodec {
+            version: None,
+            forced: None,
+            fallback: ProtocolVersion::V1,
+        }
+    }
+
+    /// Sets the version used to encode messages before one is detected.
+    pub fn with_fallback(mut self, fallback: ProtocolVersion) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    /// Overrides the detected protocol version. Frames from the controller,
+    /// including `BoardInfo`, no longer change the version once it is set.
+    pub fn set_protocol_version(&mut self, version: ProtocolVersion) {
+        self.forced = Some(version);
+    }
+}
+
+impl Default for AutoCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SbgcCodec for AutoCodec {
+    fn protocol_version(&self) -> Option<ProtocolVersion> {
+        self.forced.or(self.version)
+    }
+}
 
 #[derive(Error, Debug)]
 pub enum MessageParseError {
@@ -94,6 +181,10 @@ pub trait Message {
     where
         Self: Sized,
     {
+        if buf.is_empty() {
+            return Err(MessageParseError::InsufficientData);
+        }
+
         // use indexing so as not to consume bytes if it's not valid
         match buf[0] {
             0x3E => Message::from_v1_bytes(buf),
@@ -108,6 +199,10 @@ pub trait Message {
     {
         // use indexing so as not to consume bytes if it's not valid
 
+        if buf.len() < 4 {
+            return Err(MessageParseError::InsufficientData);
+        }
+
         // assume version byte was already checked
         let cmd = buf[1];
 
@@ -142,7 +237,7 @@ pub trait Message {
             });
         }
 
-        return Self::from_payload_bytes(cmd, payload).map(|m| (m, payload_len + 5));
+        Self::from_payload_bytes(cmd, payload).map(|m| (m, payload_len + 5))
     }
 
     fn from_v2_bytes(buf: &[u8]) -> Result<(Self, usize), MessageParseError>
@@ -151,6 +246,10 @@ pub trait Message {
     {
         // use indexing so as not to consume bytes if it's not valid
 
+        if buf.len() < 4 {
+            return Err(MessageParseError::InsufficientData);
+        }
+
         // assume version byte was already checked
         let cmd = buf[1];
 
@@ -186,7 +285,7 @@ pub trait Message {
             });
         }
 
-        return Self::from_payload_bytes(cmd, payload).map(|m| (m, payload_len + 6));
+        Self::from_payload_bytes(cmd, payload).map(|m| (m, payload_len + 6))
     }
 }
 
@@ -224,6 +323,7 @@ impl Message for OutgoingCommand {
             BoardInfo3 => CMD_BOARD_INFO_3,
             Reset => CMD_RESET,
             Control { .. } => CMD_CONTROL,
+            ControlConfig(_) => CMD_CONTROL_CONFIG,
             MotorsOn => CMD_MOTORS_ON,
             MotorsOff { .. } => CMD_MOTORS_OFF,
             ReadParams { .. } => CMD_READ_PARAMS,
@@ -233,10 +333,39 @@ impl Message for OutgoingCommand {
             ReadParamsExt3 { .. } => CMD_READ_PARAMS_EXT3,
             WriteParams(_) => CMD_WRITE_PARAMS,
             WriteParams3(_) => CMD_WRITE_PARAMS_3,
+            WriteParamsExt(_) => CMD_WRITE_PARAMS_EXT,
+            WriteParamsExt2(_) => CMD_WRITE_PARAMS_EXT2,
+            WriteParamsExt3(_) => CMD_WRITE_PARAMS_EXT3,
+            SaveParams3(_) => CMD_SAVE_PARAMS_3,
+            UseDefaults(_) => CMD_USE_DEFAULTS,
             RealtimeData3 => CMD_REALTIME_DATA_3,
+            RealtimeData4 => CMD_REALTIME_DATA_4,
+            RealtimeDataCustom(_) => CMD_REALTIME_DATA_CUSTOM,
             GetAngles => CMD_GET_ANGLES,
-            GetAnglesExt => CMD_GET_ANGLES,
-            _ => unimplemented!(),
+            GetAnglesExt => CMD_GET_ANGLES_EXT,
+            DataStreamInterval(_) => CMD_DATA_STREAM_INTERVAL,
+            ExecuteMenu(_) => CMD_EXECUTE_MENU,
+            CalibAcc(_) => CMD_CALIB_ACC,
+            CalibGyro(_) => CMD_CALIB_GYRO,
+            CalibMag(_) => CMD_CALIB_MAG,
+            CalibPoles => CMD_CALIB_POLES,
+            CalibOffset => CMD_CALIB_OFFSET,
+            CalibExtGain => CMD_CALIB_EXT_GAIN,
+            CalibMotorMagLink => CMD_CALIB_MOTOR_MAG_LINK,
+            CalibBat(_) => CMD_CALIB_BAT,
+            CalibAccExtRef(_) => CMD_CALIB_ACC_EXT_REF,
+            CalibOrientCorr(_) => CMD_CALIB_ORIENT_CORR,
+            CalibInfo(_) => CMD_CALIB_INFO,
+            AutoPid(_) => CMD_AUTO_PID,
+            AutoPid2(_) => CMD_AUTO_PID2,
+            SetAdjVarsVal(_) => CMD_SET_ADJ_VARS_VAL,
+            GetAdjVarsVal(_) => CMD_GET_ADJ_VARS_VAL,
+            AdjVarsState(_) => CMD_ADJ_VARS_STATE,
+            ReadAdjVarsCfg => CMD_READ_ADJ_VARS_CFG,
+            WriteAdjVarsCfg(_) => CMD_WRITE_ADJ_VARS_CFG,
+            ApiVirtChControl(_) => CMD_API_VIRT_CH_CONTROL,
+            ApiVirtChHighRes(_) => CMD_API_VIRT_CH_HIGH_RES,
+            Other { id } => *id,
         }
     }
 
@@ -247,6 +376,7 @@ impl Message for OutgoingCommand {
             BoardInfo3 => Bytes::default(),
             Reset => Bytes::default(),
             Control(data) => Payload::to_bytes(data),
+            ControlConfig(data) => Payload::to_bytes(data),
             MotorsOn => Bytes::default(),
             MotorsOff(data) => Payload::to_bytes(data),
             ReadParams(data) => Payload::to_bytes(data),
@@ -256,9 +386,38 @@ impl Message for OutgoingCommand {
             ReadParamsExt3(data) => Payload::to_bytes(data),
             WriteParams(data) => Payload::to_bytes(data),
             WriteParams3(data) => Payload::to_bytes(data),
+            WriteParamsExt(data) => Payload::to_bytes(data),
+            WriteParamsExt2(data) => Payload::to_bytes(data),
+            WriteParamsExt3(data) => Payload::to_bytes(data),
+            SaveParams3(data) => Payload::to_bytes(data),
+            UseDefaults(data) => Payload::to_bytes(data),
             RealtimeData3 => Bytes::default(),
+            RealtimeData4 => Bytes::default(),
+            RealtimeDataCustom(data) => Payload::to_bytes(data),
             GetAngles => Bytes::default(),
             GetAnglesExt => Bytes::default(),
+            DataStreamInterval(data) => Payload::to_bytes(data),
+            ExecuteMenu(data) => Payload::to_bytes(data),
+            CalibAcc(data) => Payload::to_bytes(data),
+            CalibGyro(data) => Payload::to_bytes(data),
+            CalibMag(data) => Payload::to_bytes(data),
+            CalibPoles => Bytes::default(),
+            CalibOffset => Bytes::default(),
+            CalibExtGain => Bytes::default(),
+            CalibMotorMagLink => Bytes::default(),
+            CalibBat(data) => Payload::to_bytes(data),
+            CalibAccExtRef(data) => Payload::to_bytes(data),
+            CalibOrientCorr(data) => Payload::to_bytes(data),
+            CalibInfo(data) => Payload::to_bytes(data),
+            AutoPid(data) => Payload::to_bytes(data),
+            AutoPid2(data) => Payload::to_bytes(data),
+            SetAdjVarsVal(data) => Payload::to_bytes(data),
+            GetAdjVarsVal(data) => Payload::to_bytes(data),
+            AdjVarsState(data) => Payload::to_bytes(data),
+            ReadAdjVarsCfg => Bytes::default(),
+            WriteAdjVarsCfg(data) => Payload::to_bytes(data),
+            ApiVirtChControl(data) => Payload::to_bytes(data),
+            ApiVirtChHighRes(data) => Payload::to_bytes(data),
             Other { id: _ } => Bytes::default(),
         }
     }
@@ -270,6 +429,9 @@ impl Message for OutgoingCommand {
         use OutgoingCommand::*;
 
         Ok(match id {
+            CMD_BOARD_INFO => BoardInfo,
+            CMD_BOARD_INFO_3 => BoardInfo3,
+            CMD_RESET => Reset,
             CMD_READ_PARAMS => ReadParams(Payload::from_bytes(bytes)?),
             CMD_READ_PARAMS_3 => ReadParams3(Payload::from_bytes(bytes)?),
             CMD_READ_PARAMS_EXT => ReadParamsExt(Payload::from_bytes(bytes)?),
@@ -277,9 +439,40 @@ impl Message for OutgoingCommand {
             CMD_READ_PARAMS_EXT3 => ReadParamsExt3(Payload::from_bytes(bytes)?),
             CMD_WRITE_PARAMS => WriteParams(Payload::from_bytes(bytes)?),
             CMD_WRITE_PARAMS_3 => WriteParams3(Payload::from_bytes(bytes)?),
+            CMD_WRITE_PARAMS_EXT => WriteParamsExt(Payload::from_bytes(bytes)?),
+            CMD_WRITE_PARAMS_EXT2 => WriteParamsExt2(Payload::from_bytes(bytes)?),
+            CMD_WRITE_PARAMS_EXT3 => WriteParamsExt3(Payload::from_bytes(bytes)?),
+            CMD_SAVE_PARAMS_3 => SaveParams3(Payload::from_bytes(bytes)?),
+            CMD_USE_DEFAULTS => UseDefaults(Payload::from_bytes(bytes)?),
+            CMD_REALTIME_DATA_3 => RealtimeData3,
+            CMD_REALTIME_DATA_4 => RealtimeData4,
+            CMD_REALTIME_DATA_CUSTOM => RealtimeDataCustom(Payload::from_bytes(bytes)?),
             CMD_GET_ANGLES => GetAngles,
             CMD_GET_ANGLES_EXT => GetAnglesExt,
+            CMD_DATA_STREAM_INTERVAL => DataStreamInterval(Payload::from_bytes(bytes)?),
+            CMD_EXECUTE_MENU => ExecuteMenu(Payload::from_bytes(bytes)?),
+            CMD_CALIB_ACC => CalibAcc(Payload::from_bytes(bytes)?),
+            CMD_CALIB_GYRO => CalibGyro(Payload::from_bytes(bytes)?),
+            CMD_CALIB_MAG => CalibMag(Payload::from_bytes(bytes)?),
+            CMD_CALIB_POLES => CalibPoles,
+            CMD_CALIB_OFFSET => CalibOffset,
+            CMD_CALIB_EXT_GAIN => CalibExtGain,
+            CMD_CALIB_MOTOR_MAG_LINK => CalibMotorMagLink,
+            CMD_CALIB_BAT => CalibBat(Payload::from_bytes(bytes)?),
+            CMD_CALIB_ACC_EXT_REF => CalibAccExtRef(Payload::from_bytes(bytes)?),
+            CMD_CALIB_ORIENT_CORR => CalibOrientCorr(Payload::from_bytes(bytes)?),
+            CMD_CALIB_INFO => CalibInfo(Payload::from_bytes(bytes)?),
+            CMD_AUTO_PID => AutoPid(Payload::from_bytes(bytes)?),
+            CMD_AUTO_PID2 => AutoPid2(Payload::from_bytes(bytes)?),
+            CMD_SET_ADJ_VARS_VAL => SetAdjVarsVal(Payload::from_bytes(bytes)?),
+            CMD_GET_ADJ_VARS_VAL => GetAdjVarsVal(Payload::from_bytes(bytes)?),
+            CMD_ADJ_VARS_STATE => AdjVarsState(Payload::from_bytes(bytes)?),
+            CMD_READ_ADJ_VARS_CFG => ReadAdjVarsCfg,
+            CMD_WRITE_ADJ_VARS_CFG => WriteAdjVarsCfg(Payload::from_bytes(bytes)?),
+            CMD_API_VIRT_CH_CONTROL => ApiVirtChControl(Payload::from_bytes(bytes)?),
+            CMD_API_VIRT_CH_HIGH_RES => ApiVirtChHighRes(Payload::from_bytes(bytes)?),
             CMD_CONTROL => Control(Payload::from_bytes(bytes)?),
+            CMD_CONTROL_CONFIG => ControlConfig(Payload::from_bytes(bytes)?),
             CMD_MOTORS_ON => MotorsOn,
             CMD_MOTORS_OFF => MotorsOff(Payload::from_bytes(bytes)?),
             _ => return Err(MessageParseError::BadCommandId { id }),
@@ -292,12 +485,23 @@ impl Message for IncomingCommand {
         match self {
             IncomingCommand::CommandConfirm(_) => CMD_CONFIRM,
             IncomingCommand::CommandError(_) => CMD_ERROR,
+            IncomingCommand::AdjVarsState(_) => CMD_ADJ_VARS_STATE,
+            IncomingCommand::AutoPid(_) => CMD_AUTO_PID,
             IncomingCommand::BoardInfo(_) => CMD_BOARD_INFO,
             IncomingCommand::BoardInfo3(_) => CMD_BOARD_INFO_3,
+            IncomingCommand::CalibInfo(_) => CMD_CALIB_INFO,
             IncomingCommand::GetAngles(_) => CMD_GET_ANGLES,
+            IncomingCommand::GetAnglesExt(_) => CMD_GET_ANGLES_EXT,
+            IncomingCommand::ReadAdjVarsCfg(_) => CMD_READ_ADJ_VARS_CFG,
             IncomingCommand::ReadParams(_) => CMD_READ_PARAMS,
             IncomingCommand::ReadParams3(_) => CMD_READ_PARAMS_3,
+            IncomingCommand::ReadParamsExt(_) => CMD_READ_PARAMS_EXT,
+            IncomingCommand::ReadParamsExt2(_) => CMD_READ_PARAMS_EXT2,
+            IncomingCommand::ReadParamsExt3(_) => CMD_READ_PARAMS_EXT3,
             IncomingCommand::RealtimeData3(_) => CMD_REALTIME_DATA_3,
+            IncomingCommand::RealtimeData4(_) => CMD_REALTIME_DATA_4,
+            IncomingCommand::RealtimeDataCustom(_) => CMD_REALTIME_DATA_CUSTOM,
+            IncomingCommand::SetAdjVarsVal(_) => CMD_SET_ADJ_VARS_VAL,
         }
     }
 
@@ -306,12 +510,23 @@ impl Message for IncomingCommand {
         match self {
             CommandConfirm(data) => Payload::to_bytes(data),
             CommandError(data) => Payload::to_bytes(data),
+            AdjVarsState(data) => Payload::to_bytes(data),
+            AutoPid(data) => Payload::to_bytes(data),
             BoardInfo(info) => Payload::to_bytes(info),
             BoardInfo3(info) => Payload::to_bytes(info),
+            CalibInfo(info) => Payload::to_bytes(info),
             GetAngles(angles) => Payload::to_bytes(angles),
+            GetAnglesExt(angles) => Payload::to_bytes(angles),
+            ReadAdjVarsCfg(cfg) => Payload::to_bytes(cfg),
             ReadParams(params) => Payload::to_bytes(params),
             ReadParams3(params) => Payload::to_bytes(params),
+            ReadParamsExt(params) => Payload::to_bytes(params),
+            ReadParamsExt2(params) => Payload::to_bytes(params),
+            ReadParamsExt3(params) => Payload::to_bytes(params),
             RealtimeData3(data) => Payload::to_bytes(data),
+            RealtimeData4(data) => Payload::to_bytes(data),
+            RealtimeDataCustom(data) => Payload::to_bytes(data),
+            SetAdjVarsVal(data) => Payload::to_bytes(data),
         }
     }
 
@@ -324,12 +539,23 @@ impl Message for IncomingCommand {
         Ok(match id {
             CMD_CONFIRM => CommandConfirm(Payload::from_bytes(bytes)?),
             CMD_ERROR => CommandError(Payload::from_bytes(bytes)?),
+            CMD_ADJ_VARS_STATE => AdjVarsState(Payload::from_bytes(bytes)?),
+            CMD_AUTO_PID => AutoPid(Payload::from_bytes(bytes)?),
             CMD_BOARD_INFO => BoardInfo(Payload::from_bytes(bytes)?),
             CMD_BOARD_INFO_3 => BoardInfo3(Payload::from_bytes(bytes)?),
+            CMD_CALIB_INFO => CalibInfo(Payload::from_bytes(bytes)?),
             CMD_GET_ANGLES => GetAngles(Payload::from_bytes(bytes)?),
+            CMD_GET_ANGLES_EXT => GetAnglesExt(Payload::from_bytes(bytes)?),
+            CMD_READ_ADJ_VARS_CFG => ReadAdjVarsCfg(Payload::from_bytes(bytes)?),
             CMD_READ_PARAMS => ReadParams(Payload::from_bytes(bytes)?),
             CMD_READ_PARAMS_3 => ReadParams3(Payload::from_bytes(bytes)?),
+            CMD_READ_PARAMS_EXT => ReadParamsExt(Payload::from_bytes(bytes)?),
+            CMD_READ_PARAMS_EXT2 => ReadParamsExt2(Payload::from_bytes(bytes)?),
+            CMD_READ_PARAMS_EXT3 => ReadParamsExt3(Payload::from_bytes(bytes)?),
             CMD_REALTIME_DATA_3 => RealtimeData3(Payload::from_bytes(bytes)?),
+            CMD_REALTIME_DATA_4 => RealtimeData4(Payload::from_bytes(bytes)?),
+            CMD_REALTIME_DATA_CUSTOM => RealtimeDataCustom(Payload::from_bytes(bytes)?),
+            CMD_SET_ADJ_VARS_VAL => SetAdjVarsVal(Payload::from_bytes(bytes)?),
             _ => return Err(MessageParseError::BadCommandId { id }),
         })
     }
@@ -375,6 +601,35 @@ impl Decoder for V2Codec {
     }
 }
 
+impl Decoder for AutoCodec {
+    type Item = IncomingCommand;
+    type Error = MessageParseError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if src.len() < 5 {
+            // not enough data to read length marker
+            return Ok(None);
+        }
+        match IncomingCommand::from_bytes(&src[..]) {
+            Ok((m, num_bytes)) => {
+                if let IncomingCommand::BoardInfo(info) = &m {
+                    self.version = Some(ProtocolVersion::for_firmware(info.firmware_version));
+                } else if self.version.is_none() {
+                    self.version = Some(match src[0] {
+                        0x24 => ProtocolVersion::V2,
+                        _ => ProtocolVersion::V1,
+                    });
+                }
+
+                src.advance(num_bytes);
+                Ok(Some(m))
+            }
+            Err(MessageParseError::InsufficientData) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
 impl Encoder<OutgoingCommand> for V1Codec {
     type Error = MessageParseError;
 
@@ -395,10 +650,173 @@ impl Encoder<OutgoingCommand> for V2Codec {
     }
 }
 
+/// Returns the length of the whole frame at the start of `buf`, according to
+/// its header, or `None` if the header is not complete.
+fn frame_len(buf: &[u8]) -> Option<usize> {
+    if buf.len() < 4 {
+        return None;
+    }
+
+    match buf[0] {
+        0x3E => Some(buf[2] as usize + 5),
+        0x24 => Some(buf[2] as usize + 6),
+        _ => None,
+    }
+}
+
+/// Counters kept by [`ResyncCodec`] while decoding.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct DecodeStats {
+    /// Number of frames that were decoded successfully.
+    pub frames: u64,
+    /// Number of bytes that were skipped while looking for a valid frame.
+    pub dropped_bytes: u64,
+    /// Number of well-formed frames that were dropped because their command
+    /// id or payload could not be parsed.
+    pub dropped_frames: u64,
+    /// Number of frames whose header checksum was wrong. They are not counted
+    /// in `dropped_frames`, but the bytes skipped over them are counted in
+    /// `dropped_bytes`.
+    pub header_checksum_failures: u64,
+    /// Number of frames whose payload checksum was wrong. They are not
+    /// counted in `dropped_frames`, but the bytes skipped over them are
+    /// counted in `dropped_bytes`.
+    pub payload_checksum_failures: u64,
+}
+
+/// Wraps another codec so that a corrupt frame does not stop the stream.
+///
+/// When the inner codec fails, the bad frame is dropped and the buffer is
+/// advanced to the next `0x3E` or `0x24` start byte. Errors are never returned
+/// from [`Decoder::decode`] (except for IO errors); they are counted in
+/// [`DecodeStats`] and, if a diagnostics channel was given, sent there.
+pub struct ResyncCodec<C> {
+    inner: C,
+    stats: DecodeStats,
+    diagnostics: Option<mpsc::UnboundedSender<MessageParseError>>,
+}
+
+impl<C> ResyncCodec<C> {
+    pub fn new(inner: C) -> Self {
+        ResyncCodec {
+            inner,
+            stats: DecodeStats::default(),
+            diagnostics: None,
+        }
+    }
+
+    /// Sends every error that causes data to be dropped to `diagnostics`.
+    pub fn with_diagnostics(
+        mut self,
+        diagnostics: mpsc::UnboundedSender<MessageParseError>,
+    ) -> Self {
+        self.diagnostics = Some(diagnostics);
+        self
+    }
+
+    pub fn stats(&self) -> DecodeStats {
+        self.stats
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<C: SbgcCodec> SbgcCodec for ResyncCodec<C> {
+    fn protocol_version(&self) -> Option<ProtocolVersion> {
+        self.inner.protocol_version()
+    }
+}
+
+impl<C> Decoder for ResyncCodec<C>
+where
+    C: Decoder<Item = IncomingCommand, Error = MessageParseError>,
+{
+    type Item = IncomingCommand;
+    type Error = MessageParseError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        loop {
+            let err = match self.inner.decode(src) {
+                Ok(Some(m)) => {
+                    self.stats.frames += 1;
+                    return Ok(Some(m));
+                }
+                Ok(None) => return Ok(None),
+                Err(MessageParseError::IoError(e)) => return Err(MessageParseError::IoError(e)),
+                Err(e) => e,
+            };
+
+            // number of bytes that are known to belong to the bad frame
+            let bad = match err {
+                MessageParseError::BadHeaderChecksum { .. } => {
+                    self.stats.header_checksum_failures += 1;
+                    1
+                }
+                MessageParseError::BadPayloadChecksum { .. } => {
+                    self.stats.payload_checksum_failures += 1;
+                    1
+                }
+                // the checksums were fine, so the frame boundary can be trusted
+                MessageParseError::BadCommandId { id } if id != 0 => {
+                    self.stats.dropped_frames += 1;
+                    frame_len(src).unwrap_or(1)
+                }
+                MessageParseError::PayloadParse(_) => {
+                    self.stats.dropped_frames += 1;
+                    frame_len(src).unwrap_or(1)
+                }
+                _ => 1,
+            };
+
+            let bad = bad.min(src.len());
+            let skip = bad
+                + src[bad..]
+                    .iter()
+                    .position(|&b| b == 0x3E || b == 0x24)
+                    .unwrap_or(src.len() - bad);
+
+            src.advance(skip);
+            self.stats.dropped_bytes += skip as u64;
+
+            if let Some(diagnostics) = &self.diagnostics {
+                let _ = diagnostics.send(err);
+            }
+        }
+    }
+}
+
+impl<C> Encoder<OutgoingCommand> for ResyncCodec<C>
+where
+    C: Encoder<OutgoingCommand, Error = MessageParseError>,
+{
+    type Error = MessageParseError;
+
+    fn encode(&mut self, item: OutgoingCommand, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.inner.encode(item, dst)
+    }
+}
+
+impl Encoder<OutgoingCommand> for AutoCodec {
+    type Error = MessageParseError;
+
+    fn encode(&mut self, item: OutgoingCommand, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let bytes = match self.protocol_version().unwrap_or(self.fallback) {
+            ProtocolVersion::V1 => item.to_v1_bytes(),
+            ProtocolVersion::V2 => item.to_v2_bytes(),
+        };
+        dst.put_slice(&bytes[..]);
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{Message, OutgoingCommand, ParamsQuery};
+    use crate::*;
+    use bytes::{Bytes, BytesMut};
     use std::error::Error;
+    use tokio_util::codec::{Decoder, Encoder};
 
     #[test]
     fn sanity() -> Result<(), Box<dyn Error>> {
@@ -413,4 +831,304 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn execute_menu() -> Result<(), Box<dyn Error>> {
+        let cmd = OutgoingCommand::ExecuteMenu(MenuCommand::LookDown);
+        let packet = cmd.to_v1_bytes();
+        assert_eq!(&packet[..], &[0x3E, 69, 0x01, 70, 17, 17]);
+        assert_eq!(OutgoingCommand::from_bytes(&packet[..])?.0, cmd);
+
+        let cmd = OutgoingCommand::ExecuteMenu(MenuCommand::SwitchImu);
+        let packet = cmd.to_v1_bytes();
+        assert_eq!(&packet[..], &[0x3E, 69, 0x01, 70, 65, 65]);
+        assert_eq!(OutgoingCommand::from_bytes(&packet[..])?.0, cmd);
+
+        let packet = [0x3E, 69, 0x01, 70, 0xFF, 0xFF];
+        assert_eq!(
+            OutgoingCommand::from_bytes(&packet[..])?.0,
+            OutgoingCommand::ExecuteMenu(MenuCommand::Other(0xFF))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn calib() -> Result<(), Box<dyn Error>> {
+        let mut query = CalibQuery::new(ImuType::Frame, CalibAction::Extended);
+        query.time_ms = 0x0102;
+        let cmd = OutgoingCommand::CalibAcc(query);
+        let packet = cmd.to_v1_bytes();
+        assert_eq!(&packet[..4], &[0x3E, 65, 12, 77]);
+        assert_eq!(&packet[4..8], &[2, 3, 0x02, 0x01]);
+        assert_eq!(OutgoingCommand::from_bytes(&packet[..])?.0, cmd);
+
+        let cmd = OutgoingCommand::CalibBat(CalibBatQuery {
+            actual_voltage: 1260,
+        });
+        assert_eq!(OutgoingCommand::from_bytes(&cmd.to_v1_bytes())?.0, cmd);
+
+        let cmd = OutgoingCommand::CalibPoles;
+        assert_eq!(&cmd.to_v1_bytes()[..], &[0x3E, 80, 0, 80, 0]);
+        Ok(())
+    }
+
+    #[test]
+    fn calib_info() -> Result<(), Box<dyn Error>> {
+        let cmd = OutgoingCommand::CalibInfo(CalibInfoQuery {
+            imu: ImuType::Frame,
+        });
+        assert_eq!(&cmd.to_v1_bytes()[..], &[0x3E, 49, 1, 50, 2, 2]);
+
+        let mut payload = vec![0u8; 41];
+        payload[0] = 55; // PROGRESS
+        payload[1] = 1; // IMU_TYPE
+        payload[2..4].copy_from_slice(&512i16.to_le_bytes()); // ACC_DATA roll
+        payload[12] = (-5i8) as u8; // IMU_TEMP_CELSIUS
+        payload[26..30].copy_from_slice(&1_700_000_000u32.to_le_bytes()); // ACC_CALIB_TIME
+        payload[37] = 0b1001_1010; // IMU_SENSOR_STATUS
+        payload[40] = 200; // CALIB_QUALITY yaw
+        let info = CalibInfo::from_bytes(Bytes::from(payload))?;
+        assert_eq!(info.progress, 55);
+        assert_eq!(info.imu, ImuType::Main);
+        assert_eq!(info.acc_data.roll, 512);
+        assert_eq!(info.imu_temp, -5);
+        assert_eq!(info.acc_calib_time, 1_700_000_000);
+        assert_eq!(
+            info.sensor_status,
+            ImuSensorStatus::GyroPresent
+                | ImuSensorStatus::AccCalibrated
+                | ImuSensorStatus::GyroCalibrated
+                | ImuSensorStatus::Error
+        );
+        assert_eq!(info.calib_quality.yaw, 200);
+
+        let msg = IncomingCommand::CalibInfo(info);
+        assert_eq!(IncomingCommand::from_bytes(&msg.to_v1_bytes())?.0, msg);
+        Ok(())
+    }
+
+    #[test]
+    fn auto_pid() -> Result<(), Box<dyn Error>> {
+        let cmd = OutgoingCommand::AutoPid(AutoPidQuery::new(2));
+        let packet = cmd.to_v1_bytes();
+        assert_eq!(&packet[..8], &[0x3E, 35, 19, 54, 2, 0x0F, 128, 0]);
+        assert_eq!(OutgoingCommand::from_bytes(&packet[..])?.0, cmd);
+
+        let axis = AutoPid2AxisConfig {
+            enabled: true,
+            gain: 100,
+            stimulus: 300,
+            effective_freq: 40,
+            problem_freq: 0,
+            problem_margin: 0,
+            reserved: [0; 6],
+        };
+        let cmd = OutgoingCommand::AutoPid2(AutoPid2Query {
+            action: AutoPid2Action::Start,
+            reserved1: [0; 10],
+            cfg_version: 1,
+            axes: RollPitchYaw::from((axis, axis, axis)),
+            flags: AutoPid2Flags::TuneLpf | AutoPid2Flags::SendGui,
+            startup_cfg: 0,
+            reserved2: [0; 22],
+        });
+        let packet = cmd.to_v1_bytes();
+        assert_eq!(packet[2], 77);
+        assert_eq!(OutgoingCommand::from_bytes(&packet[..])?.0, cmd);
+
+        let mut payload = vec![0u8; 23];
+        payload[3] = 7; // I roll
+        payload[9..11].copy_from_slice(&150u16.to_le_bytes()); // LPF_FREQ roll
+        payload[15..17].copy_from_slice(&12u16.to_le_bytes()); // ITERATION_CNT
+        let progress = AutoPidProgress::from_bytes(Bytes::from(payload))?;
+        assert_eq!(progress.i.roll, 7);
+        assert_eq!(progress.lpf_freq.roll, 150);
+        assert_eq!(progress.iteration, 12);
+        Ok(())
+    }
+
+    #[test]
+    fn adj_vars() -> Result<(), Box<dyn Error>> {
+        let cmd = OutgoingCommand::GetAdjVarsVal(AdjVarsQuery {
+            vars: vec![AdjVar::PPitch, AdjVar::FollowInDband],
+        });
+        assert_eq!(&cmd.to_v1_bytes()[..], &[0x3E, 64, 3, 67, 2, 1, 66, 69]);
+
+        let values = AdjVarsValues {
+            values: vec![
+                AdjVarValue {
+                    var: AdjVar::PPitch,
+                    value: 25,
+                },
+                AdjVarValue {
+                    var: AdjVar::FrameHeadingAngle,
+                    value: -1800,
+                },
+            ],
+        };
+        let msg = IncomingCommand::SetAdjVarsVal(values.clone());
+        let packet = msg.to_v1_bytes();
+        assert_eq!(&packet[4..10], &[2, 1, 25, 0, 0, 0]);
+        assert_eq!(IncomingCommand::from_bytes(&packet[..])?.0, msg);
+        let cmd = OutgoingCommand::SetAdjVarsVal(values);
+        assert_eq!(OutgoingCommand::from_bytes(&cmd.to_v1_bytes())?.0, cmd);
+
+        // NUM_PARAMS claims more values than there are
+        assert!(AdjVarsValues::from_bytes(Bytes::from_static(&[2, 1, 25, 0, 0, 0])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn adj_vars_cfg() -> Result<(), Box<dyn Error>> {
+        let mut payload = vec![0u8; 128];
+        payload[0] = 0x21; // TRIGGER_SRC_CH[0]: ADC1
+        payload[3] = 17; // TRIGGER_ACTION3[0]: LookDown
+        payload[4] = 200; // TRIGGER_ACTION4[0]: unknown to this crate
+        payload[60 + 4 * 2] = 0x85; // ANALOG_SRC[2]: virtual channel 5
+        payload[60 + 4 * 2 + 1] = 43; // VAR_ID[2]: PID_GAIN_ROLL
+        payload[60 + 4 * 2 + 3] = 200; // MAX_VAL[2]
+        let mut cfg = AdjVarsCfg::from_bytes(Bytes::from(payload.clone()))?;
+        assert_eq!(
+            cfg.trigger_slots[0].source,
+            RcMap::Analog {
+                channel: RcMapAnalogChannel::ADC1
+            }
+        );
+        assert_eq!(cfg.trigger_slots[0].action_3, MenuCommand::LookDown);
+        assert_eq!(cfg.trigger_slots[0].action_4, MenuCommand::Other(200));
+        assert_eq!(cfg.analog_slots[2].source, RcMap::Virtual { channel: 5 });
+        assert_eq!(cfg.analog_slots[2].var, AdjVar::PidGainRoll);
+        assert_eq!(cfg.analog_slots[2].max_val, 200);
+        assert_eq!(&cfg.to_bytes()[..], &payload[..]);
+
+        let msg = IncomingCommand::ReadAdjVarsCfg(cfg.clone());
+        assert_eq!(IncomingCommand::from_bytes(&msg.to_v1_bytes())?.0, msg);
+
+        assert!(cfg.validate().is_ok());
+        cfg.set(
+            "ANALOG_SRC[2]",
+            &FieldValue::Enum("Serial { channel: 20 }".into()),
+        )?;
+        assert_eq!(cfg.analog_slots[2].source, RcMap::Serial { channel: 20 });
+        assert_eq!(cfg.validate().unwrap_err().len(), 1);
+
+        let cmd = OutgoingCommand::WriteAdjVarsCfg(cfg);
+        assert_eq!(OutgoingCommand::from_bytes(&cmd.to_v1_bytes())?.0, cmd);
+        Ok(())
+    }
+
+    #[test]
+    fn virt_ch() -> Result<(), Box<dyn Error>> {
+        let mut channels = [None; VIRT_CH_COUNT];
+        channels[0] = Some(500);
+        channels[31] = Some(-500);
+        let cmd = OutgoingCommand::ApiVirtChControl(VirtChControl { channels });
+        let packet = cmd.to_v1_bytes();
+        assert_eq!(&packet[..8], &[0x3E, 45, 64, 109, 0xF4, 0x01, 0xF0, 0xD8]);
+        assert_eq!(OutgoingCommand::from_bytes(&packet[..])?.0, cmd);
+
+        let cmd = OutgoingCommand::ApiVirtChHighRes(VirtChHighRes {
+            channels: vec![Some(16384), None],
+        });
+        let packet = cmd.to_v1_bytes();
+        assert_eq!(&packet[..], &[0x3E, 116, 4, 120, 0x00, 0x40, 0x00, 0x80, 0xC0]);
+        assert_eq!(OutgoingCommand::from_bytes(&packet[..])?.0, cmd);
+        Ok(())
+    }
+
+    #[test]
+    fn control_config() -> Result<(), Box<dyn Error>> {
+        assert_eq!(ControlConfig::SIZE, 41);
+
+        let mut config = ControlConfig {
+            timeout_ms: 500,
+            flags: ControlConfigFlags::NoConfirm.into(),
+            ..ControlConfig::default()
+        };
+        config.axes.yaw.acc_limit = 300;
+        let cmd = OutgoingCommand::ControlConfig(config);
+        let packet = cmd.to_v1_bytes();
+        assert_eq!(&packet[..4], &[0x3E, 90, 41, 131]);
+        assert_eq!(&packet[4..6], &[0xF4, 0x01]);
+        assert_eq!(&packet[28..30], &[0x2C, 0x01]);
+        assert_eq!(&packet[33..35], &[0x01, 0x00]);
+        assert_eq!(OutgoingCommand::from_bytes(&packet[..])?.0, cmd);
+        Ok(())
+    }
+
+    #[test]
+    fn resync() {
+        let confirm = IncomingCommand::CommandConfirm(ConfirmData {
+            cmd_id: 77,
+            data: None,
+        });
+        let frame = confirm.to_v1_bytes();
+
+        let mut corrupt = frame.to_vec();
+        corrupt[4] ^= 0xFF;
+
+        let mut src = BytesMut::new();
+        src.extend_from_slice(&[0x00, 0x13, 0x37]);
+        src.extend_from_slice(&frame);
+        src.extend_from_slice(&corrupt);
+        src.extend_from_slice(&frame);
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut codec = ResyncCodec::new(V1Codec).with_diagnostics(tx);
+
+        assert_eq!(codec.decode(&mut src).unwrap(), Some(confirm.clone()));
+        assert_eq!(codec.decode(&mut src).unwrap(), Some(confirm));
+        assert_eq!(codec.decode(&mut src).unwrap(), None);
+        assert!(src.is_empty());
+
+        let stats = codec.stats();
+        assert_eq!(stats.frames, 2);
+        assert_eq!(stats.payload_checksum_failures, 1);
+        assert_eq!(stats.dropped_frames, 0);
+        assert_eq!(stats.dropped_bytes, 3 + frame.len() as u64);
+
+        assert!(rx.try_recv().is_ok());
+        assert!(rx.try_recv().is_ok());
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn auto_version() {
+        let confirm = IncomingCommand::CommandConfirm(ConfirmData {
+            cmd_id: 77,
+            data: None,
+        });
+
+        let mut codec = AutoCodec::new();
+        let mut dst = BytesMut::new();
+        codec.encode(OutgoingCommand::MotorsOn, &mut dst).unwrap();
+        assert_eq!(dst[0], 0x3E, "should fall back to V1");
+
+        let mut src = BytesMut::from(&confirm.to_v2_bytes()[..]);
+        assert_eq!(codec.decode(&mut src).unwrap(), Some(confirm));
+        assert_eq!(codec.protocol_version(), Some(ProtocolVersion::V2));
+
+        let mut dst = BytesMut::new();
+        codec.encode(OutgoingCommand::MotorsOn, &mut dst).unwrap();
+        assert_eq!(dst[0], 0x24, "should reply in V2");
+    }
+
+    #[test]
+    fn forced_version() {
+        // BoardInfo from firmware 2.71, which supports V2
+        let payload: &[u8] = &[30, 0x9E, 0x0A, 0, 0x01, 0x00, 0, 0, 0, 0, 0];
+        let info = BoardInfo::from_bytes(Bytes::from_static(payload)).unwrap();
+        let info = IncomingCommand::BoardInfo(info);
+
+        let mut codec = AutoCodec::new();
+        codec.set_protocol_version(ProtocolVersion::V1);
+
+        let mut src = BytesMut::from(&info.to_v2_bytes()[..]);
+        assert_eq!(codec.decode(&mut src).unwrap(), Some(info));
+        assert_eq!(codec.protocol_version(), Some(ProtocolVersion::V1));
+
+        let mut dst = BytesMut::new();
+        codec.encode(OutgoingCommand::MotorsOn, &mut dst).unwrap();
+        assert_eq!(dst[0], 0x3E, "should keep the forced version");
+    }
 }
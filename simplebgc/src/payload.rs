@@ -1,4 +1,5 @@
 use bytes::{Buf, Bytes};
+use enumflags2::{BitFlag, BitFlags};
 use thiserror::Error;
 
 #[derive(Error, Clone, Debug, PartialEq)]
@@ -7,6 +8,29 @@ pub enum PayloadParseError {
     InvalidFlags { name: String },
     #[error("invalid enum value for {name}")]
     InvalidEnum { name: String },
+    #[error("insufficient payload for {name}, expected {expected} bytes, got {actual}")]
+    InsufficientPayload {
+        name: String,
+        expected: usize,
+        actual: usize,
+    },
+}
+
+/// Returns an error if `b` holds fewer than `expected` bytes.
+pub(crate) fn ensure_remaining(
+    b: &impl Buf,
+    name: &str,
+    expected: usize,
+) -> Result<(), PayloadParseError> {
+    if b.remaining() < expected {
+        return Err(PayloadParseError::InsufficientPayload {
+            name: name.into(),
+            expected,
+            actual: b.remaining(),
+        });
+    }
+
+    Ok(())
 }
 
 pub trait Payload {
@@ -21,11 +45,39 @@ pub trait Payload {
         Self: Sized;
 }
 
+/// Implemented by payloads whose serialized size is known at compile time.
+/// The `BgcPayload` derive implements this automatically.
+pub trait PayloadSize {
+    /// Number of bytes this payload takes up when every field is present.
+    const SIZE: usize;
+
+    /// Number of bytes this payload takes up when every optional field is
+    /// absent.
+    const MIN_SIZE: usize = Self::SIZE;
+}
+
+impl PayloadSize for u8 {
+    const SIZE: usize = 1;
+}
+
+impl PayloadSize for i8 {
+    const SIZE: usize = 1;
+}
+
+impl PayloadSize for u16 {
+    const SIZE: usize = 2;
+}
+
+impl PayloadSize for i16 {
+    const SIZE: usize = 2;
+}
+
 impl Payload for u8 {
     fn from_bytes(mut b: Bytes) -> Result<Self, PayloadParseError>
     where
         Self: Sized,
     {
+        ensure_remaining(&b, "u8", 1)?;
         Ok(b.get_u8())
     }
 
@@ -42,6 +94,7 @@ impl Payload for i8 {
     where
         Self: Sized,
     {
+        ensure_remaining(&b, "i8", 1)?;
         Ok(b.get_i8())
     }
 
@@ -58,7 +111,8 @@ impl Payload for u16 {
     where
         Self: Sized,
     {
-        Ok(b.get_u16())
+        ensure_remaining(&b, "u16", 2)?;
+        Ok(b.get_u16_le())
     }
 
     fn to_bytes(&self) -> Bytes
@@ -74,7 +128,8 @@ impl Payload for i16 {
     where
         Self: Sized,
     {
-        Ok(b.get_i16())
+        ensure_remaining(&b, "i16", 2)?;
+        Ok(b.get_i16_le())
     }
 
     fn to_bytes(&self) -> Bytes
@@ -84,3 +139,88 @@ impl Payload for i16 {
         Bytes::copy_from_slice(&[*self as u8, (*self >> 8) as u8])
     }
 }
+
+impl<T: BitFlag<Numeric = u8>> PayloadSize for BitFlags<T> {
+    const SIZE: usize = 1;
+}
+
+/// Lets one byte of flags be used as a sub-payload, e.g. with one byte per axis.
+impl<T: BitFlag<Numeric = u8>> Payload for BitFlags<T> {
+    fn from_bytes(mut b: Bytes) -> Result<Self, PayloadParseError>
+    where
+        Self: Sized,
+    {
+        ensure_remaining(&b, std::any::type_name::<T>(), 1)?;
+        BitFlags::from_bits(b.get_u8()).map_err(|_| PayloadParseError::InvalidFlags {
+            name: std::any::type_name::<T>().into(),
+        })
+    }
+
+    fn to_bytes(&self) -> Bytes
+    where
+        Self: Sized,
+    {
+        Bytes::copy_from_slice(&[self.bits()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use bytes::Bytes;
+
+    #[test]
+    fn truncated_payload() {
+        // BOARD_VER is present, but only half of FIRMWARE_VER
+        let b = Bytes::from_static(&[0x0A, 0x5E]);
+        assert_eq!(
+            BoardInfo::from_bytes(b),
+            Err(PayloadParseError::InsufficientPayload {
+                name: "FIRMWARE_VER".into(),
+                expected: 2,
+                actual: 1,
+            })
+        );
+
+        let b = Bytes::from_static(&[0x01, 0x02, 0x03]);
+        assert!(RollPitchYaw::<i16>::from_bytes(b).is_err());
+
+        assert!(ConfirmData::from_bytes(Bytes::new()).is_err());
+        assert!(ControlData::from_bytes(Bytes::from_static(&[0x02])).is_err());
+    }
+
+    #[test]
+    fn optional_fields() {
+        // board info from firmware that predates BOARD_FEATURES_EXT
+        let b = Bytes::from_static(&[30, 0x9E, 0x0A, 0, 0x01, 0x00, 0, 0, 0, 0, 0]);
+        let info = BoardInfo::from_bytes(b.clone()).unwrap();
+        assert_eq!(info.firmware_version, 2718);
+        assert_eq!(info.board_features_ext, None);
+        assert_eq!(info.base_firmware_version, None);
+        assert_eq!(info.to_bytes(), b);
+
+        let b = Bytes::from_static(&[
+            30, 0x9E, 0x0A, 0, 0x01, 0x00, 0, 0, 0, 0, 0, 0x02, 0x00, 0, 0, 0, 0x9E, 0x0A,
+        ]);
+        let info = BoardInfo::from_bytes(b.clone()).unwrap();
+        assert_eq!(info.board_features_ext, Some(2));
+        assert_eq!(info.base_firmware_version, Some(2718));
+        assert_eq!(info.to_bytes(), b);
+
+        // a later field cannot be written without the ones before it
+        let info = BoardInfo {
+            board_features_ext: None,
+            ..info
+        };
+        assert_eq!(info.to_bytes(), b.slice(..11));
+    }
+
+    #[test]
+    fn primitive_round_trip() {
+        let b = Payload::to_bytes(&-1234i16);
+        assert_eq!(&b[..], &[0x2E, 0xFB]);
+        assert_eq!(<i16 as Payload>::from_bytes(b), Ok(-1234));
+        let b = Bytes::from_static(&[0x34, 0x12]);
+        assert_eq!(<u16 as Payload>::from_bytes(b), Ok(0x1234));
+    }
+}